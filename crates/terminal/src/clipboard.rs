@@ -0,0 +1,26 @@
+use std::fmt;
+
+/// OSC 52 escape sequence that asks the terminal to place `content` on the system clipboard.
+/// Works over SSH and inside tmux (with `set-clipboard on`) without a clipboard daemon.
+pub struct Clipboard<'a>(pub &'a str);
+
+impl fmt::Display for Clipboard<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\x1B]52;c;{}\x07", base64::encode(self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_content_in_osc_52() {
+        assert_eq!(Clipboard("hi").to_string(), "\x1B]52;c;aGk=\x07");
+    }
+
+    #[test]
+    fn encodes_nothing_as_an_empty_payload() {
+        assert_eq!(Clipboard("").to_string(), "\x1B]52;c;\x07");
+    }
+}
@@ -1,9 +1,13 @@
 use std::cmp;
+use std::collections::HashMap;
 use std::fmt;
 use std::io::{self, Write};
 use std::iter;
 use std::ops;
 
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
 use crate::style;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -57,19 +61,71 @@ impl fmt::Display for Point {
     }
 }
 
-#[derive(Debug, Default, Copy, Clone)]
+/// A single terminal column. A cell usually holds one character, but it can hold a whole
+/// grapheme cluster, like a letter with combining accents or an emoji sequence. Wide characters,
+/// such as CJK ideographs, are followed by a continuation cell covering their second column.
+#[derive(Debug, Default, Clone)]
 pub struct Cell {
     pos: Point,
-    content: char,
+    content: String,
 }
 
 impl Cell {
     pub fn new(pos: Point, content: char) -> Self {
-        Self { pos, content }
+        Self { pos, content: content.to_string() }
+    }
+
+    pub fn grapheme(pos: Point, grapheme: &str) -> Self {
+        Self { pos, content: grapheme.to_string() }
+    }
+
+    pub fn continuation(pos: Point) -> Self {
+        Self { pos, content: String::new() }
+    }
+
+    pub fn pos(&self) -> Point {
+        self.pos
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// The content as a single character, if it is one.
+    pub fn char(&self) -> Option<char> {
+        let mut chars = self.content.chars();
+        match (chars.next(), chars.next()) {
+            (Some(char), None) => Some(char),
+            _ => None,
+        }
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.content == " "
+    }
+
+    pub fn is_wide(&self) -> bool {
+        self.content.width() > 1
+    }
+
+    pub fn is_continuation(&self) -> bool {
+        self.content.is_empty()
+    }
+
+    /// The position of the other half of a wide character, which can't be drawn over
+    /// separately.
+    pub fn partner(&self) -> Option<Point> {
+        if self.is_continuation() && self.pos.x > 0 {
+            Some(Point::new(self.pos.x - 1, self.pos.y))
+        } else if self.is_wide() {
+            Some(Point::new(self.pos.x + 1, self.pos.y))
+        } else {
+            None
+        }
     }
 
     pub fn clear(&mut self) {
-        self.content = ' ';
+        self.content = " ".to_string();
     }
 }
 
@@ -93,9 +149,26 @@ impl Segment {
     pub fn from_str(start: Point, str: &str, format: style::Format) -> Self {
         let mut cells = Vec::new();
         let mut cursor = start;
-        for char in str.as_bytes() {
-            cells.push(Cell::new(cursor, (*char) as char));
-            cursor.move_right();
+        for (grapheme, width) in graphemes(str) {
+            cursor = place(&mut cells, cursor, grapheme, width);
+        }
+
+        Self { cells, format }
+    }
+
+    /// Lays out `str` within the box from `start` to `end`, inclusive, wrapping onto the next
+    /// row whenever a character doesn't fit and dropping whatever overflows the last row.
+    pub fn from_str_wrapped(start: Point, end: Point, str: &str, format: style::Format) -> Self {
+        let mut cells = Vec::new();
+        let mut cursor = start;
+        for (grapheme, width) in graphemes(str) {
+            if cursor.x + width - 1 > end.x {
+                cursor.move_to(start.x, cursor.y + 1);
+            }
+            if cursor.y > end.y || cursor.x + width - 1 > end.x {
+                break;
+            }
+            cursor = place(&mut cells, cursor, grapheme, width);
         }
 
         Self { cells, format }
@@ -109,6 +182,30 @@ impl Segment {
         self.cells.clear();
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    pub fn contains(&self, pos: Point) -> bool {
+        self.cells.iter().any(|cell| cell.pos == pos)
+    }
+
+    /// Moves every cell by `dx` columns and `dy` rows, stopping at the top and left edges.
+    pub fn translate(&mut self, dx: i32, dy: i32) {
+        for cell in &mut self.cells {
+            cell.pos.x = (i32::from(cell.pos.x) + dx).max(0) as u16;
+            cell.pos.y = (i32::from(cell.pos.y) + dy).max(0) as u16;
+        }
+    }
+
+    pub fn format(&self) -> style::Format {
+        self.format
+    }
+
     pub fn set_format(&mut self, format: style::Format) {
         self.format = format;
     }
@@ -150,7 +247,7 @@ impl From<Segment> for String {
             cursor.move_to(start.x, cursor.y);
             while cursor.x <= end.x {
                 match segment.cells.iter().find(|cell| cell.pos == cursor) {
-                    Some(cell) => output.push(cell.content),
+                    Some(cell) => output.push_str(&cell.content),
                     None => output.push(' '),
                 }
                 cursor.move_right();
@@ -191,6 +288,27 @@ impl fmt::Display for Segment {
     }
 }
 
+/// Splits `str` into grapheme clusters along with the number of columns each one covers.
+fn graphemes(str: &str) -> impl Iterator<Item = (&str, u16)> {
+    str.graphemes(true).map(|grapheme| (grapheme, grapheme.width().clamp(1, 2) as u16))
+}
+
+/// Adds the cells for a grapheme at `cursor` and returns where the next one goes.
+fn place(cells: &mut Vec<Cell>, mut cursor: Point, grapheme: &str, width: u16) -> Point {
+    cells.push(Cell::grapheme(cursor, grapheme));
+    cursor.move_right();
+    if width > 1 {
+        cells.push(Cell::continuation(cursor));
+        cursor.move_right();
+    }
+    cursor
+}
+
+/// The number of columns `str` covers in a terminal.
+pub fn text_width(str: &str) -> usize {
+    graphemes(str).map(|(_, width)| width as usize).sum()
+}
+
 pub trait Erase {
     fn erase(&mut self, segment: &mut Segment) -> io::Result<()>;
 }
@@ -198,11 +316,169 @@ pub trait Erase {
 impl<W: Write> Erase for W {
     fn erase(&mut self, segment: &mut Segment) -> io::Result<()> {
         segment.cells.iter_mut().for_each(|cell| cell.clear());
+        segment.format = Default::default();
         write!(self, "{}", segment)
     }
 }
 
-#[derive(Debug)]
+const BRAILLE_BLANK: u32 = 0x2800;
+
+/// Dots set within each cell of a canvas drawn at Braille resolution, where every cell is a
+/// 2x4 dot matrix rendered as a single Unicode Braille pattern.
+#[derive(Debug, Default, Clone)]
+pub struct Braille {
+    cells: HashMap<Point, u8>,
+}
+
+impl Braille {
+    pub fn new() -> Self {
+        Self { cells: HashMap::new() }
+    }
+
+    /// The dot a cell maps to; the left column, second row of its matrix.
+    pub fn dot(cell: Point) -> Point {
+        Point::new(cell.x * 2, cell.y * 4 + 1)
+    }
+
+    pub fn set(&mut self, dot: Point) {
+        let bit = match (dot.x % 2, dot.y % 4) {
+            (0, 3) => 0x40,
+            (1, 3) => 0x80,
+            (0, row) => 1 << row,
+            (_, row) => 1 << (row + 3),
+        };
+        *self.cells.entry(Point::new(dot.x / 2, dot.y / 4)).or_insert(0) |= bit;
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    pub fn to_segment(&self, format: style::Format) -> Segment {
+        let mut segment = Segment::new();
+        segment.set_format(format);
+        for (pos, dots) in &self.cells {
+            segment.add(Cell::new(*pos, braille_char(*dots)));
+        }
+        segment
+    }
+
+    /// Combines two cell contents so that Braille patterns drawn over each other keep the
+    /// dots of both. Anything else is simply replaced by `above`.
+    pub fn merge(below: char, above: char) -> char {
+        match (braille_dots(below), braille_dots(above)) {
+            (Some(below), Some(above)) => braille_char(below | above),
+            _ => above,
+        }
+    }
+}
+
+fn braille_char(dots: u8) -> char {
+    std::char::from_u32(BRAILLE_BLANK + u32::from(dots)).expect("invalid braille pattern")
+}
+
+fn braille_dots(char: char) -> Option<u8> {
+    let offset = (char as u32).checked_sub(BRAILLE_BLANK)?;
+    if offset <= 0xFF {
+        Some(offset as u8)
+    } else {
+        None
+    }
+}
+
+const UPPER_HALF: char = '▀';
+const LOWER_HALF: char = '▄';
+const FULL_BLOCK: char = '█';
+
+type HalfBlock = (Option<style::Color>, Option<style::Color>);
+
+/// Colours of the two vertically stacked pixels within each cell of a canvas drawn in half
+/// blocks, where a cell's foreground and background colours paint one pixel each.
+#[derive(Debug, Default, Clone)]
+pub struct Pixels {
+    cells: HashMap<Point, HalfBlock>,
+}
+
+impl Pixels {
+    pub fn new() -> Self {
+        Self { cells: HashMap::new() }
+    }
+
+    /// The pixel a cell maps to; its upper half.
+    pub fn pixel(cell: Point) -> Point {
+        Point::new(cell.x, cell.y * 2)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn set(&mut self, pixel: Point, color: style::Color) {
+        let cell = self.cells.entry(Point::new(pixel.x, pixel.y / 2)).or_insert((None, None));
+        match pixel.y % 2 {
+            0 => cell.0 = Some(color),
+            _ => cell.1 = Some(color),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    /// One segment per cell, as each needs its own colours.
+    pub fn to_segments(&self) -> Vec<Segment> {
+        self.cells
+            .iter()
+            .filter_map(|(pos, half_block)| {
+                let (content, format) = encode_half_block(*half_block)?;
+                let mut segment = Segment::new();
+                segment.set_format(format);
+                segment.add(Cell::new(*pos, content));
+                Some(segment)
+            })
+            .collect()
+    }
+
+    /// The colours of the upper and lower pixels of a cell, if it's drawn in half blocks.
+    pub fn decode(content: char, format: style::Format) -> Option<HalfBlock> {
+        let background = match format.bg_color {
+            style::Color::Reset => None,
+            color => Some(color),
+        };
+        match content {
+            UPPER_HALF => Some((Some(format.fg_color), background)),
+            LOWER_HALF => Some((background, Some(format.fg_color))),
+            FULL_BLOCK => Some((Some(format.fg_color), Some(format.fg_color))),
+            _ => None,
+        }
+    }
+
+    /// Combines two half block cells so that pixels painted over each other keep the colours
+    /// of both. Anything else is simply replaced by `above`.
+    pub fn merge(
+        below: (char, style::Format),
+        above: (char, style::Format),
+    ) -> (char, style::Format) {
+        match (Pixels::decode(below.0, below.1), Pixels::decode(above.0, above.1)) {
+            (Some((top, bottom)), Some((new_top, new_bottom))) => {
+                encode_half_block((new_top.or(top), new_bottom.or(bottom))).unwrap_or(above)
+            }
+            _ => above,
+        }
+    }
+}
+
+fn encode_half_block(half_block: HalfBlock) -> Option<(char, style::Format)> {
+    match half_block {
+        (Some(top), bottom) => {
+            Some((UPPER_HALF, style::Format::new(bottom.unwrap_or(style::Color::Reset), top)))
+        }
+        (None, Some(bottom)) => Some((LOWER_HALF, style::Format::new(style::Color::Reset, bottom))),
+        (None, None) => None,
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct CharSet {
     pub stationary: char,
     pub up: char,
@@ -278,4 +554,351 @@ impl Tracer {
 
         segment
     }
+
+    /// Traces a line between two cells at Braille resolution, setting each dot along the way.
+    pub fn trace_braille(&self, from: Point, to: Point, braille: &mut Braille) {
+        bresenham(Braille::dot(from), Braille::dot(to), |dot| braille.set(dot));
+    }
+
+    /// Traces a line between two cells at half block resolution, painting each pixel along
+    /// the way with `color`.
+    pub fn trace_pixels(&self, from: Point, to: Point, color: style::Color, pixels: &mut Pixels) {
+        bresenham(Pixels::pixel(from), Pixels::pixel(to), |pixel| pixels.set(pixel, color));
+    }
+
+    /// Traces the outline of the ellipse bounded by the rectangle between `from` and `to`,
+    /// using the midpoint algorithm. Points are walked clockwise from the top so that each one
+    /// can be given the character for the direction between its neighbours.
+    pub fn ellipse(&self, from: Point, to: Point) -> Segment {
+        let (start, end) = (
+            Point::new(from.x.min(to.x), from.y.min(to.y)),
+            Point::new(from.x.max(to.x), from.y.max(to.y)),
+        );
+        let (width, height) = (end.x - start.x, end.y - start.y);
+        let (rx, ry) = (width / 2, height / 2);
+        if rx == 0 || ry == 0 {
+            return self.trace(from, to);
+        }
+
+        let quadrant = ellipse_quadrant(i64::from(rx), i64::from(ry));
+        let (left, right) = (start.x + rx, start.x + rx + width % 2);
+        let (top, bottom) = (start.y + ry, start.y + ry + height % 2);
+
+        let mut points: Vec<Point> = Vec::new();
+        let mut push = |point: Point| {
+            if points.last() != Some(&point) {
+                points.push(point);
+            }
+        };
+        quadrant.iter().for_each(|(dx, dy)| push(Point::new(right + dx, top - dy)));
+        quadrant.iter().rev().for_each(|(dx, dy)| push(Point::new(right + dx, bottom + dy)));
+        quadrant.iter().for_each(|(dx, dy)| push(Point::new(left - dx, bottom + dy)));
+        quadrant.iter().rev().for_each(|(dx, dy)| push(Point::new(left - dx, top - dy)));
+        if points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+
+        let mut segment = Segment::new();
+        for (i, point) in points.iter().enumerate() {
+            let previous = points[(i + points.len() - 1) % points.len()];
+            let next = points[(i + 1) % points.len()];
+            segment.add(Cell::new(*point, self.char_set.next(previous, next)));
+        }
+
+        segment
+    }
+}
+
+fn bresenham(from: Point, to: Point, mut plot: impl FnMut(Point)) {
+    let (x0, y0, x1, y1) = (i32::from(from.x), i32::from(from.y), i32::from(to.x), i32::from(to.y));
+    let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+    let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+
+    let (mut x, mut y, mut error) = (x0, y0, dx + dy);
+    loop {
+        plot(Point::new(x as u16, y as u16));
+        if x == x1 && y == y1 {
+            break;
+        }
+
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += sx;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += sy;
+        }
+    }
+}
+
+/// Offsets from the centre of an ellipse with radii `rx` and `ry` for one quadrant, ordered
+/// from the top (`(0, ry)`) to the side (`(rx, 0)`).
+fn ellipse_quadrant(rx: i64, ry: i64) -> Vec<(u16, u16)> {
+    let (rx2, ry2) = (rx * rx, ry * ry);
+    let (mut x, mut y) = (0, ry);
+    let mut points = vec![(x, y)];
+
+    let mut decision = (ry2 - rx2 * ry) as f64 + rx2 as f64 / 4.0;
+    while ry2 * x < rx2 * y {
+        x += 1;
+        if decision < 0.0 {
+            decision += (2 * ry2 * x + ry2) as f64;
+        } else {
+            y -= 1;
+            decision += (2 * ry2 * x - 2 * rx2 * y + ry2) as f64;
+        }
+        points.push((x, y));
+    }
+
+    decision = ry2 as f64 * (x as f64 + 0.5).powi(2) + (rx2 * (y - 1) * (y - 1) - rx2 * ry2) as f64;
+    while y > 0 {
+        y -= 1;
+        if decision > 0.0 {
+            decision += (rx2 - 2 * rx2 * y) as f64;
+        } else {
+            x += 1;
+            decision += (2 * ry2 * x - 2 * rx2 * y + rx2) as f64;
+        }
+        points.push((x, y));
+    }
+
+    points.into_iter().map(|(x, y)| (x as u16, y as u16)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(segment: &Segment) -> Vec<(u16, u16)> {
+        let mut positions: Vec<(u16, u16)> =
+            segment.cells().iter().map(|cell| (cell.pos().x, cell.pos().y)).collect();
+        positions.sort_unstable();
+        positions
+    }
+
+    #[test]
+    fn ellipse_fills_its_box_symmetrically() {
+        let tracer = Tracer::default();
+        // Even and odd widths and heights, dragged either way
+        for (from, to) in [((2, 1), (8, 5)), ((2, 1), (9, 6)), ((9, 6), (2, 1)), ((3, 3), (6, 5))] {
+            let (from, to) = (Point::from(from), Point::from(to));
+            let ellipse = tracer.ellipse(from, to);
+            let (start, end) = (
+                Point::new(from.x.min(to.x), from.y.min(to.y)),
+                Point::new(from.x.max(to.x), from.y.max(to.y)),
+            );
+            assert_eq!(ellipse.boundaries(), Some((start, end)), "{:?} to {:?}", from, to);
+
+            let positions = positions(&ellipse);
+            let mut unique = positions.clone();
+            unique.dedup();
+            assert_eq!(unique, positions, "cells drawn twice from {:?} to {:?}", from, to);
+            for &(x, y) in &positions {
+                let mirrored = (start.x + end.x - x, start.y + end.y - y);
+                assert!(positions.binary_search(&(start.x + end.x - x, y)).is_ok());
+                assert!(positions.binary_search(&(x, start.y + end.y - y)).is_ok());
+                assert!(positions.binary_search(&mirrored).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn ellipse_draws_each_point_towards_its_neighbours() {
+        let ellipse = Tracer::default().ellipse((1, 1).into(), (9, 5).into());
+        assert_eq!(
+            String::from(ellipse),
+            "  /___\\  \n /     \\ \n|       |\n \\     / \n  \\___/  \n"
+        );
+    }
+
+    #[test]
+    fn flat_ellipse_is_a_line() {
+        let tracer = Tracer::default();
+        for (from, to) in [((3, 1), (3, 6)), ((3, 1), (4, 6)), ((1, 2), (8, 2)), ((1, 2), (8, 3))] {
+            let (from, to) = (Point::from(from), Point::from(to));
+            assert_eq!(
+                String::from(tracer.ellipse(from, to)),
+                String::from(tracer.trace(from, to)),
+                "{:?} to {:?}",
+                from,
+                to
+            );
+        }
+    }
+
+    fn braille_cells(braille: &Braille) -> Vec<(u16, u16, char)> {
+        let mut cells: Vec<(u16, u16, char)> = braille
+            .to_segment(Default::default())
+            .cells()
+            .iter()
+            .map(|cell| (cell.pos().x, cell.pos().y, cell.char().unwrap()))
+            .collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn braille_dots_map_to_their_bits() {
+        // Left column top to bottom, then the right, with the bottom row last
+        let expected = [
+            ((0, 0), '\u{2801}'),
+            ((0, 1), '\u{2802}'),
+            ((0, 2), '\u{2804}'),
+            ((1, 0), '\u{2808}'),
+            ((1, 1), '\u{2810}'),
+            ((1, 2), '\u{2820}'),
+            ((0, 3), '\u{2840}'),
+            ((1, 3), '\u{2880}'),
+        ];
+        for ((x, y), char) in expected {
+            let mut braille = Braille::new();
+            // In the cell at (3, 2)
+            braille.set(Point::new(6 + x, 8 + y));
+            assert_eq!(braille_cells(&braille), [(3, 2, char)], "dot ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn braille_dots_in_one_cell_combine() {
+        let mut braille = Braille::new();
+        braille.set(Point::new(0, 3));
+        braille.set(Point::new(1, 3));
+        braille.set(Point::new(2, 0));
+        assert_eq!(braille_cells(&braille), [(0, 0, '\u{28C0}'), (1, 0, '\u{2801}')]);
+        assert_eq!(Braille::dot(Point::new(1, 0)), Point::new(2, 1));
+    }
+
+    #[test]
+    fn braille_merge_keeps_the_dots_of_both() {
+        assert_eq!(Braille::merge('\u{2801}', '\u{2880}'), '\u{2881}');
+        assert_eq!(Braille::merge('\u{28FF}', '\u{2800}'), '\u{28FF}');
+        // Anything that isn't a pattern is replaced
+        assert_eq!(Braille::merge('x', '\u{2801}'), '\u{2801}');
+        assert_eq!(Braille::merge('\u{2801}', 'x'), 'x');
+    }
+
+    #[test]
+    fn pixels_encode_two_colours_per_cell_and_decode_back() {
+        let mut pixels = Pixels::new();
+        pixels.set(Point::new(2, 6), style::Color::Blue);
+        pixels.set(Point::new(2, 7), style::Color::Red);
+        pixels.set(Point::new(4, 7), style::Color::Green);
+
+        let mut cells: Vec<(Point, char, style::Format)> = pixels
+            .to_segments()
+            .iter()
+            .map(|segment| {
+                (segment.cells()[0].pos(), segment.cells()[0].char().unwrap(), segment.format())
+            })
+            .collect();
+        cells.sort_unstable_by_key(|(pos, _, _)| pos.x);
+        assert_eq!(
+            cells,
+            [
+                (
+                    Point::new(2, 3),
+                    UPPER_HALF,
+                    style::Format::new(style::Color::Red, style::Color::Blue)
+                ),
+                (
+                    Point::new(4, 3),
+                    LOWER_HALF,
+                    style::Format::new(style::Color::Reset, style::Color::Green)
+                ),
+            ]
+        );
+        for (_, char, format) in cells {
+            let decoded = Pixels::decode(char, format);
+            assert_eq!(decoded.and_then(encode_half_block), Some((char, format)));
+        }
+        assert_eq!(
+            Pixels::decode(UPPER_HALF, style::Format::new(style::Color::Red, style::Color::Blue)),
+            Some((Some(style::Color::Blue), Some(style::Color::Red)))
+        );
+        assert_eq!(Pixels::decode('x', Default::default()), None);
+    }
+
+    #[test]
+    fn pixels_merge_top_over_bottom() {
+        let top = (UPPER_HALF, style::Format::new(style::Color::Reset, style::Color::Red));
+        let bottom = (LOWER_HALF, style::Format::new(style::Color::Reset, style::Color::Blue));
+        let both = (UPPER_HALF, style::Format::new(style::Color::Blue, style::Color::Red));
+        assert_eq!(Pixels::merge(top, bottom), both);
+        assert_eq!(Pixels::merge(bottom, top), both);
+
+        // Pixels painted on top win, and a full block covers both
+        let green = (UPPER_HALF, style::Format::new(style::Color::Reset, style::Color::Green));
+        assert_eq!(
+            Pixels::merge(both, green),
+            (UPPER_HALF, style::Format::new(style::Color::Blue, style::Color::Green))
+        );
+        let full = (FULL_BLOCK, style::Format::new(style::Color::Reset, style::Color::Green));
+        assert_eq!(
+            Pixels::merge(both, full),
+            (UPPER_HALF, style::Format::new(style::Color::Green, style::Color::Green))
+        );
+
+        // Anything else is drawn over
+        let text = ('x', style::Format::default());
+        assert_eq!(Pixels::merge(both, text), text);
+        assert_eq!(Pixels::merge(text, both), both);
+    }
+
+    fn layout(segment: &Segment) -> Vec<((u16, u16), &str)> {
+        segment.cells().iter().map(|cell| ((cell.pos().x, cell.pos().y), cell.content())).collect()
+    }
+
+    #[test]
+    fn wide_characters_get_continuation_cells() {
+        let segment = Segment::from_str(Point::new(1, 1), "日本x", Default::default());
+        assert_eq!(
+            layout(&segment),
+            [((1, 1), "日"), ((2, 1), ""), ((3, 1), "本"), ((4, 1), ""), ((5, 1), "x")]
+        );
+        assert_eq!(text_width("日本x"), 5);
+    }
+
+    #[test]
+    fn combining_marks_stay_with_their_letter() {
+        let segment = Segment::from_str(Point::new(1, 1), "e\u{301}x", Default::default());
+        assert_eq!(layout(&segment), [((1, 1), "e\u{301}"), ((2, 1), "x")]);
+        assert_eq!(text_width("e\u{301}x"), 2);
+    }
+
+    #[test]
+    fn wrapping_moves_a_wide_character_that_does_not_fit() {
+        let wrap = |text| {
+            Segment::from_str_wrapped(Point::new(1, 1), Point::new(3, 2), text, Default::default())
+        };
+        assert_eq!(
+            layout(&wrap("ab語c")),
+            [((1, 1), "a"), ((2, 1), "b"), ((1, 2), "語"), ((2, 2), ""), ((3, 2), "c")]
+        );
+        // Whatever overflows the last row is dropped
+        assert_eq!(
+            layout(&wrap("語語語")),
+            [((1, 1), "語"), ((2, 1), ""), ((1, 2), "語"), ((2, 2), "")]
+        );
+
+        let narrow =
+            Segment::from_str_wrapped(Point::new(1, 1), Point::new(1, 3), "語", Default::default());
+        assert!(narrow.is_empty());
+    }
+
+    #[test]
+    fn partners_point_across_a_wide_character() {
+        let segment = Segment::from_str(Point::new(4, 2), "語a", Default::default());
+        let partners: Vec<Option<Point>> = segment.cells().iter().map(Cell::partner).collect();
+        assert_eq!(partners, [Some(Point::new(5, 2)), Some(Point::new(4, 2)), None]);
+    }
+
+    #[test]
+    fn text_keeps_columns_aligned_around_wide_characters() {
+        let mut segment = Segment::from_str(Point::new(1, 1), "日本", Default::default());
+        segment += Segment::from_str(Point::new(1, 2), "abcd", Default::default());
+        segment += Segment::from_str(Point::new(2, 3), "語", Default::default());
+        assert_eq!(String::from(segment), "日本\nabcd\n 語 \n");
+    }
 }
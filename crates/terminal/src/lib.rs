@@ -1,7 +1,9 @@
 pub mod grid;
 
+mod clipboard;
 mod style;
 mod terminal;
 
+pub use clipboard::*;
 pub use style::*;
 pub use terminal::*;
@@ -8,17 +8,107 @@ pub type Color = style::Color;
 pub static RESET_FORMAT: Format = Format {
     bg_color: Color::Reset,
     fg_color: Color::Reset,
+    attributes: Attributes::NONE,
 };
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Attribute {
+    Bold,
+    Dim,
+    Italic,
+    Underline,
+    Reverse,
+    Strikethrough,
+}
+
+impl Attribute {
+    pub const ALL: [Attribute; 6] = [
+        Attribute::Bold,
+        Attribute::Dim,
+        Attribute::Italic,
+        Attribute::Underline,
+        Attribute::Reverse,
+        Attribute::Strikethrough,
+    ];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl From<Attribute> for style::Attribute {
+    fn from(attribute: Attribute) -> Self {
+        match attribute {
+            Attribute::Bold => style::Attribute::Bold,
+            Attribute::Dim => style::Attribute::Dim,
+            Attribute::Italic => style::Attribute::Italic,
+            Attribute::Underline => style::Attribute::Underlined,
+            Attribute::Reverse => style::Attribute::Reverse,
+            Attribute::Strikethrough => style::Attribute::CrossedOut,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Attributes(u8);
+
+impl Attributes {
+    pub const NONE: Attributes = Attributes(0);
+
+    pub fn has(self, attribute: Attribute) -> bool {
+        self.0 & attribute.bit() != 0
+    }
+
+    pub fn set(&mut self, attribute: Attribute) {
+        self.0 |= attribute.bit();
+    }
+
+    pub fn unset(&mut self, attribute: Attribute) {
+        self.0 &= !attribute.bit();
+    }
+
+    pub fn toggle(&mut self, attribute: Attribute) {
+        self.0 ^= attribute.bit();
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Attribute> {
+        Attribute::ALL.iter().copied().filter(move |attribute| self.has(*attribute))
+    }
+}
+
+impl From<Attribute> for Attributes {
+    fn from(attribute: Attribute) -> Self {
+        Self(attribute.bit())
+    }
+}
+
+impl From<Attributes> for u8 {
+    fn from(attributes: Attributes) -> Self {
+        attributes.0
+    }
+}
+
+impl From<u8> for Attributes {
+    fn from(bits: u8) -> Self {
+        Self(bits)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Format {
     pub bg_color: Color,
     pub fg_color: Color,
+    pub attributes: Attributes,
 }
 
 impl Format {
     pub fn new(bg_color: Color, fg_color: Color) -> Self {
-        Self { bg_color, fg_color }
+        Self { bg_color, fg_color, attributes: Attributes::NONE }
+    }
+
+    pub fn with_attributes(mut self, attributes: Attributes) -> Self {
+        self.attributes = attributes;
+        self
     }
 }
 
@@ -30,12 +120,18 @@ impl Default for Format {
 
 impl fmt::Display for Format {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Attributes are cumulative, so clear whatever the previous format left behind first
         crossterm::queue!(
             f,
+            style::SetAttribute(style::Attribute::Reset),
             style::SetBackgroundColor(self.bg_color),
             style::SetForegroundColor(self.fg_color),
         )
         .map_err(|_| fmt::Error)?;
+
+        for attribute in self.attributes.iter() {
+            crossterm::queue!(f, style::SetAttribute(attribute.into())).map_err(|_| fmt::Error)?;
+        }
         Ok(())
     }
 }
@@ -1,8 +1,9 @@
 use std::convert::{TryFrom, TryInto};
 use std::error;
 use std::fmt;
-use std::io;
+use std::io::{self, Write};
 use std::result;
+use std::str;
 use std::time;
 
 use crossterm::event;
@@ -10,6 +11,8 @@ use crossterm::terminal;
 use crossterm::tty::IsTty;
 use crossterm::{ErrorKind, ExecutableCommand};
 
+use crate::clipboard;
+
 type SomeResult<T = ()> = result::Result<T, Box<dyn error::Error>>;
 type ExecResult<'a> = SomeResult<&'a mut Terminal>;
 
@@ -34,47 +37,57 @@ impl Terminal {
         Ok(Some(event::read()?.try_into()?))
     }
 
-    pub fn enable_raw_mode(&mut self) -> ExecResult {
+    pub fn size(&self) -> SomeResult<(u16, u16)> {
+        Ok(terminal::size()?)
+    }
+
+    pub fn enable_raw_mode(&mut self) -> ExecResult<'_> {
         terminal::enable_raw_mode()?;
         Ok(self)
     }
 
-    pub fn disable_raw_mode(&mut self) -> ExecResult {
+    pub fn disable_raw_mode(&mut self) -> ExecResult<'_> {
         terminal::disable_raw_mode()?;
         Ok(self)
     }
 
-    pub fn hide_cursor(&mut self) -> ExecResult {
+    pub fn hide_cursor(&mut self) -> ExecResult<'_> {
         self.stdout.execute(crossterm::cursor::Hide)?;
         Ok(self)
     }
 
-    pub fn show_cursor(&mut self) -> ExecResult {
+    pub fn show_cursor(&mut self) -> ExecResult<'_> {
         self.stdout.execute(crossterm::cursor::Show)?;
         Ok(self)
     }
 
-    pub fn enter_alt_screen(&mut self) -> ExecResult {
+    pub fn enter_alt_screen(&mut self) -> ExecResult<'_> {
         self.stdout.execute(terminal::EnterAlternateScreen)?;
         Ok(self)
     }
 
-    pub fn leave_alt_screen(&mut self) -> ExecResult {
+    pub fn leave_alt_screen(&mut self) -> ExecResult<'_> {
         self.stdout.execute(terminal::LeaveAlternateScreen)?;
         Ok(self)
     }
 
-    pub fn enable_mouse_capture(&mut self) -> ExecResult {
+    pub fn enable_mouse_capture(&mut self) -> ExecResult<'_> {
         self.stdout.execute(event::EnableMouseCapture)?;
         Ok(self)
     }
 
-    pub fn disable_mouse_capture(&mut self) -> ExecResult {
+    pub fn disable_mouse_capture(&mut self) -> ExecResult<'_> {
         self.stdout.execute(event::DisableMouseCapture)?;
         Ok(self)
     }
 
-    pub fn clear(&mut self) -> ExecResult {
+    pub fn copy_to_clipboard(&mut self, content: &str) -> ExecResult<'_> {
+        write!(self.stdout, "{}", clipboard::Clipboard(content))?;
+        self.stdout.flush()?;
+        Ok(self)
+    }
+
+    pub fn clear(&mut self) -> ExecResult<'_> {
         self.stdout.execute(terminal::Clear(terminal::ClearType::All))?;
         Ok(self)
     }
@@ -109,6 +122,17 @@ impl fmt::Display for InputError {
     }
 }
 
+#[derive(Debug)]
+pub struct ParseKeyError(String);
+
+impl error::Error for ParseKeyError {}
+
+impl fmt::Display for ParseKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown key `{}`", self.0)
+    }
+}
+
 pub enum Event {
     Key(KeyEvent),
     Mouse(MouseEvent),
@@ -126,12 +150,85 @@ impl TryFrom<event::Event> for Event {
     }
 }
 
-#[derive(Default)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct KeyEvent {
-    pub char: char,
+    pub key: Key,
     pub modifier: Option<KeyModifier>,
 }
 
+impl KeyEvent {
+    pub fn new(key: Key, modifier: Option<KeyModifier>) -> Self {
+        Self { key, modifier }
+    }
+}
+
+/// Parses key names as written in config files, like `q`, `S`, `ctrl+s`, `space`, `esc` or `f1`.
+impl str::FromStr for KeyEvent {
+    type Err = ParseKeyError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let (modifier, key) = match name.find('+') {
+            Some(index) if name[..index].eq_ignore_ascii_case("ctrl") && index + 1 < name.len() => {
+                (Some(KeyModifier::Ctrl), &name[index + 1..])
+            }
+            _ => (None, name),
+        };
+
+        let mut chars = key.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(char), None) => Key::Char(char),
+            _ => match key.to_ascii_lowercase().as_str() {
+                "space" => Key::Char(' '),
+                "enter" => Key::Enter,
+                "tab" => Key::Tab,
+                "backspace" => Key::Backspace,
+                "esc" => Key::Esc,
+                "up" => Key::Up,
+                "down" => Key::Down,
+                lower => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=12) => Key::F(n),
+                    _ => return Err(ParseKeyError(name.to_string())),
+                },
+            },
+        };
+
+        Ok(Self { key, modifier })
+    }
+}
+
+impl fmt::Display for KeyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(KeyModifier::Ctrl) = self.modifier {
+            write!(f, "Ctrl+")?;
+        }
+        match self.key {
+            Key::Char(' ') => write!(f, "Space"),
+            Key::Char(char) => write!(f, "{}", char),
+            Key::Enter => write!(f, "Enter"),
+            Key::Tab => write!(f, "Tab"),
+            Key::Backspace => write!(f, "Backspace"),
+            Key::Esc => write!(f, "Esc"),
+            Key::Up => write!(f, "Up"),
+            Key::Down => write!(f, "Down"),
+            Key::F(n) => write!(f, "F{}", n),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Esc,
+    Up,
+    Down,
+    /// A function key, from F1 to F12.
+    F(u8),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum KeyModifier {
     Ctrl,
 }
@@ -141,16 +238,25 @@ impl TryFrom<event::KeyEvent> for KeyEvent {
 
     fn try_from(event: event::KeyEvent) -> Result<Self, Self::Error> {
         let event::KeyEvent { code, modifiers } = event;
-        match code {
-            event::KeyCode::Char(char) => Ok(Self {
-                char,
-                modifier: match modifiers {
-                    event::KeyModifiers::CONTROL => Some(KeyModifier::Ctrl),
-                    _ => None,
-                },
-            }),
-            _ => Err(InputError::UnsupportedEvent),
-        }
+        let key = match code {
+            event::KeyCode::Char(char) => Key::Char(char),
+            event::KeyCode::Enter => Key::Enter,
+            event::KeyCode::Tab => Key::Tab,
+            event::KeyCode::Backspace => Key::Backspace,
+            event::KeyCode::Esc => Key::Esc,
+            event::KeyCode::Up => Key::Up,
+            event::KeyCode::Down => Key::Down,
+            event::KeyCode::F(n) => Key::F(n),
+            _ => return Err(InputError::UnsupportedEvent),
+        };
+
+        Ok(Self {
+            key,
+            modifier: match modifiers {
+                event::KeyModifiers::CONTROL => Some(KeyModifier::Ctrl),
+                _ => None,
+            },
+        })
     }
 }
 
@@ -159,6 +265,7 @@ pub struct MouseEvent {
     pub action: MouseAction,
 }
 
+/// The column and row under the mouse, counting from 1 like cursor positions.
 pub type MousePos = (u16, u16);
 
 pub enum MouseAction {
@@ -177,7 +284,8 @@ impl TryFrom<event::MouseEvent> for MouseEvent {
     type Error = InputError;
 
     fn try_from(event: event::MouseEvent) -> Result<Self, Self::Error> {
-        let mouse = |x, y, action| Ok(MouseEvent::new((x, y), action));
+        // Crossterm counts from 0, but cells are drawn at 1-based cursor positions
+        let mouse = |x: u16, y: u16, action| Ok(MouseEvent::new((x + 1, y + 1), action));
         match event {
             event::MouseEvent::Down(_, x, y, _) => mouse(x, y, MouseAction::Press),
             event::MouseEvent::Up(_, x, y, _) => mouse(x, y, MouseAction::Release),
@@ -186,3 +294,47 @@ impl TryFrom<event::MouseEvent> for MouseEvent {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str) -> KeyEvent {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_single_characters() {
+        assert_eq!(parse("q"), KeyEvent::new(Key::Char('q'), None));
+        assert_eq!(parse("+"), KeyEvent::new(Key::Char('+'), None));
+    }
+
+    #[test]
+    fn parses_named_keys_in_any_case() {
+        assert_eq!(parse("Space"), KeyEvent::new(Key::Char(' '), None));
+        assert_eq!(parse("ESC"), KeyEvent::new(Key::Esc, None));
+        assert_eq!(parse("backspace"), KeyEvent::new(Key::Backspace, None));
+        assert_eq!(parse("f12"), KeyEvent::new(Key::F(12), None));
+    }
+
+    #[test]
+    fn parses_ctrl_combinations() {
+        assert_eq!(parse("ctrl+z"), KeyEvent::new(Key::Char('z'), Some(KeyModifier::Ctrl)));
+        assert_eq!(parse("Ctrl++"), KeyEvent::new(Key::Char('+'), Some(KeyModifier::Ctrl)));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        for name in ["", "ctrl+", "f13", "shift+a", "escape"] {
+            let error = name.parse::<KeyEvent>().unwrap_err();
+            assert_eq!(error.to_string(), format!("unknown key `{}`", name));
+        }
+    }
+
+    #[test]
+    fn displays_keys_as_they_are_parsed() {
+        for name in ["q", "Space", "Ctrl+s", "Esc", "F1", "Up"] {
+            assert_eq!(parse(name).to_string(), name);
+        }
+    }
+}
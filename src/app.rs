@@ -1,26 +1,76 @@
+use std::fs;
 use std::io::{self, Write};
+use std::mem;
+use std::path;
+use std::time;
 
 use terminal::grid::{self, Erase};
+use terminal::Key;
 
 use crate::canvas;
+use crate::command;
+use crate::config;
 use crate::export;
+use crate::keymap::{self, Action};
 use crate::menu;
+use crate::recovery;
+use crate::theme;
 
 pub struct Opts {
-    char_set: grid::CharSet,
+    config: config::Config,
+    export_mode: export::Mode,
+    visible_only: bool,
+    files: Vec<String>,
 }
 
 impl Opts {
-    pub fn new(char_set: grid::CharSet) -> Self {
-        Self { char_set }
+    pub fn new(
+        config: config::Config,
+        export_mode: export::Mode,
+        visible_only: bool,
+        files: Vec<String>,
+    ) -> Self {
+        Self { config, export_mode, visible_only, files }
     }
 }
 
+enum Query {
+    SaveAs,
+    Overwrite(String),
+    Quit,
+    Restore(Vec<recovery::Sketch>),
+    NewLayer,
+    Text(canvas::Shape),
+    BrushChar,
+    /// A command line, browsing the history at the given position.
+    Command(usize),
+}
+
+/// What to do once a command has run.
+enum Outcome {
+    Continue(Option<String>),
+    Open(Buffer),
+    Quit,
+}
+
+/// An open sketch. The one being drawn on is swapped out into the loop's own `canvas` and
+/// `file_name`, leaving a blank buffer in its place.
+struct Buffer {
+    canvas: canvas::Canvas,
+    file_name: Option<String>,
+}
+
 pub fn launch(opts: Opts) -> crate::Result {
     if !terminal::is_tty() {
         return Err("stream is not TTY".into());
     }
 
+    let (themes, theme_index) = opts.config.themes()?;
+    let keymap = keymap::Keymap::new(&opts.config.keys).map_err(|error| {
+        let path = config::file_path().unwrap_or_default();
+        format!("{}: [keys] {}", path.display(), error)
+    })?;
+
     let mut terminal = terminal::Terminal::default();
 
     terminal
@@ -30,7 +80,7 @@ pub fn launch(opts: Opts) -> crate::Result {
         .hide_cursor()?
         .clear()?;
 
-    let result = run_canvas(&mut terminal, opts.char_set);
+    let result = run_canvas(&mut terminal, opts, keymap, themes, theme_index);
 
     terminal
         .clear()?
@@ -42,96 +92,801 @@ pub fn launch(opts: Opts) -> crate::Result {
     result
 }
 
-fn run_canvas(terminal: &mut terminal::Terminal, char_set: grid::CharSet) -> crate::Result {
+fn run_canvas(
+    terminal: &mut terminal::Terminal,
+    opts: Opts,
+    keymap: keymap::Keymap,
+    themes: Vec<(String, theme::Theme)>,
+    mut theme_index: usize,
+) -> crate::Result {
     let mut screen = io::stdout();
-    let mut canvas = canvas::Canvas::new();
+    let mut buffers = match opts.files.as_slice() {
+        [] => vec![Buffer { canvas: new_canvas(&opts), file_name: None }],
+        files => files.iter().map(|name| open(name, &opts)).collect::<crate::Result<_>>()?,
+    };
+    let mut current = 0;
+    let mut canvas = mem::replace(&mut buffers[current].canvas, new_canvas(&opts));
+    let mut file_name = buffers[current].file_name.take();
+    let mut clip: Option<canvas::Clip> = None;
+    let mut guides = opts.config.guides;
+    let mut snap = false;
+    // Columns and rows of the shapes already drawn, gathered when a snapped drag starts
+    let mut alignment: (Vec<u16>, Vec<u16>) = (Vec::new(), Vec::new());
+    let mut alignment_print = grid::Segment::new();
     let mut sketch = grid::Segment::new();
-    let mut toolbar = menu::ToolBar::new();
-    let mut tool = canvas::Tool::default();
-    let mut file_name: Option<String> = None;
-    let mut file_name_print = grid::Segment::new();
+    let mut theme = themes[theme_index].1;
+    let (width, height) = terminal.size()?;
+    let mut toolbar = menu::ToolBar::new(&keymap, theme, width);
+    let mut tool = opts.config.tool;
+    toolbar.highlight_tool(tool);
+    let mut format = terminal::Format::default();
+    let mut brush = canvas::Brush::default();
+    let mut resolution = canvas::Resolution::default();
+    let mut dots = grid::Braille::new();
+    let mut pixels = grid::Pixels::new();
+    let mut handles_print = grid::Segment::new();
+    let mut prompt: Option<(Query, menu::Prompt)> = None;
+    let mut help: Option<menu::Help> = None;
+    let mut minimap: Option<menu::Minimap> = None;
+    let mut history: Vec<String> = Vec::new();
+    // The toolbar entry the mouse was pressed on
+    let mut pressed: Option<Action> = None;
+    // Set while dragging on a layer that can't be drawn on, or from the minimap
+    let mut refused = false;
+
+    let mut status_bar = menu::StatusBar::new(height, width, theme);
+    // Between the toolbar and the status bar
+    let canvas_area = (page_origin(), grid::Point::new(width, height - 1));
+    let new_prompt =
+        |label: &str, theme| menu::Prompt::new(label, grid::Point::new(1, height), width, theme);
+    let mut last_autosave = time::Instant::now();
 
-    let tracer = grid::Tracer::new(char_set);
+    let mut recovery = recovery::Session::new();
+    let (sketches, errors) = recovery.load();
+    if !errors.is_empty() {
+        status_bar.notify(&errors.join("; "));
+    }
+    if !sketches.is_empty() {
+        prompt = Some((
+            Query::Restore(sketches),
+            new_prompt("Restore unsaved sketches from a crashed session? (y/n) ", theme),
+        ));
+    }
 
     loop {
         match terminal.read_event() {
             Ok(event) => {
                 if let Some(event) = event {
+                    let mut action = None;
                     match event {
-                        terminal::Event::Key(terminal::KeyEvent { char, modifier }) => {
-                            match (char, modifier) {
-                                ('q', _) => break,
-                                ('u', _) => {
-                                    if let Some(mut segment) = canvas.undo() {
-                                        screen.erase(&mut segment)?;
-                                    }
-                                }
-                                ('k', _) => {
-                                    canvas.clear();
-                                    sketch.clear();
-                                    terminal.clear()?;
-                                }
-                                ('s', Some(terminal::KeyModifier::Ctrl)) => {
-                                    let blueprint: grid::Segment = canvas.snapshot().iter().sum();
-                                    match file_name {
-                                        Some(ref name) => export::to_file_as(blueprint, name)?,
-                                        None => {
-                                            let name = export::to_file(blueprint)?;
-                                            file_name_print = grid::Segment::from_str(
-                                                (1, 300).into(),
-                                                &name,
-                                                terminal::Format::new(
-                                                    terminal::Color::Black,
-                                                    terminal::Color::Green,
-                                                ),
+                        terminal::Event::Key(terminal::KeyEvent { key, .. })
+                            if prompt.is_some() =>
+                        {
+                            let (query, mut input) = prompt.take().expect("prompt is open");
+                            match (&query, key) {
+                                (Query::Quit, Key::Char('s')) => {
+                                    let saved = buffers
+                                        .iter_mut()
+                                        .enumerate()
+                                        .filter(|(index, _)| *index != current)
+                                        .map(|(_, buffer)| {
+                                            (&mut buffer.canvas, &mut buffer.file_name)
+                                        })
+                                        .chain(Some((&mut canvas, &mut file_name)))
+                                        .filter(|(sketch, _)| sketch.is_dirty())
+                                        .try_for_each(|(sketch, name)| {
+                                            *name = Some(save(sketch, name.as_deref(), &opts)?);
+                                            crate::Result::Ok(())
+                                        });
+                                    match saved {
+                                        Ok(()) => break,
+                                        // Stay put, so that nothing is lost
+                                        Err(error) => {
+                                            status_bar.notify(&format!("Couldn't save: {}", error));
+                                            prompt = Some((query, input));
+                                        }
+                                    }
+                                }
+                                (Query::Quit, Key::Char('d')) => break,
+                                (Query::Quit, Key::Char('c')) | (_, Key::Esc) => {
+                                    screen.erase(&mut input.segment())?
+                                }
+                                (Query::Quit, _) => prompt = Some((query, input)),
+                                (Query::BrushChar, Key::Char(char)) => {
+                                    screen.erase(&mut input.segment())?;
+                                    brush.char = char;
+                                    toolbar.set_brush(brush);
+                                }
+                                (Query::BrushChar, _) => prompt = Some((query, input)),
+                                (Query::Restore(_), key) => {
+                                    screen.erase(&mut input.segment())?;
+                                    if let (Query::Restore(sketches), Key::Char('y')) = (query, key)
+                                    {
+                                        // Each in its own tab, the first taking over the
+                                        // blank one the session started with
+                                        let mut sketches = sketches.into_iter();
+                                        let blank = buffers.len() == 1
+                                            && file_name.is_none()
+                                            && canvas.record().is_empty();
+                                        if let Some(sketch) =
+                                            blank.then(|| sketches.next()).flatten()
+                                        {
+                                            canvas.restore(sketch.canvas);
+                                            file_name = sketch.file_name;
+                                        }
+                                        let first = buffers.len();
+                                        for sketch in sketches {
+                                            let mut restored = new_canvas(&opts);
+                                            restored.restore(sketch.canvas);
+                                            buffers.push(Buffer {
+                                                canvas: restored,
+                                                file_name: sketch.file_name,
+                                            });
+                                        }
+                                        if !blank && first < buffers.len() {
+                                            switch(
+                                                &mut buffers,
+                                                &mut current,
+                                                first,
+                                                &mut canvas,
+                                                &mut file_name,
                                             );
-
-                                            file_name = Some(name);
                                         }
+                                        terminal.clear()?;
                                     }
                                 }
-                                (n, _) if n.is_digit(10) => {
-                                    tool = match n {
-                                        '3' => canvas::Tool::Erase,
-                                        '2' => canvas::Tool::Line,
-                                        _ => canvas::Tool::Plot,
+                                (_, Key::Enter) => {
+                                    screen.erase(&mut input.segment())?;
+                                    let target = match query {
+                                        _ if input.value().is_empty() => None,
+                                        Query::SaveAs => {
+                                            let path = opts.config.save_dir.join(input.value());
+                                            let name = path.to_string_lossy().into_owned();
+                                            if path.exists() && file_name.as_ref() != Some(&name) {
+                                                let label = format!("Overwrite {}? (y/n) ", name);
+                                                prompt = Some((
+                                                    Query::Overwrite(name),
+                                                    new_prompt(&label, theme),
+                                                ));
+                                                None
+                                            } else {
+                                                Some(name)
+                                            }
+                                        }
+                                        Query::Overwrite(name) if input.value() == "y" => {
+                                            Some(name)
+                                        }
+                                        Query::NewLayer => {
+                                            canvas.add_layer(input.value());
+                                            None
+                                        }
+                                        Query::Text(canvas::Shape::TextBox(from, to, _)) => {
+                                            let text = input.value().to_string();
+                                            canvas.add_shape(
+                                                canvas::Shape::TextBox(from, to, text),
+                                                format,
+                                            );
+                                            None
+                                        }
+                                        Query::Command(_) => {
+                                            let line = input.value().to_string();
+                                            let outcome = line.parse().and_then(|command| {
+                                                run_command(
+                                                    command,
+                                                    &mut canvas,
+                                                    &mut file_name,
+                                                    &mut guides,
+                                                    terminal,
+                                                    &opts,
+                                                )
+                                            });
+                                            if history.last() != Some(&line) {
+                                                history.push(line);
+                                            }
+                                            match outcome {
+                                                Ok(Outcome::Quit) if buffers.len() > 1 => {
+                                                    close(
+                                                        &mut buffers,
+                                                        &mut current,
+                                                        &mut canvas,
+                                                        &mut file_name,
+                                                    );
+                                                    terminal.clear()?;
+                                                }
+                                                Ok(Outcome::Quit) => break,
+                                                Ok(Outcome::Open(buffer)) => {
+                                                    buffers.push(buffer);
+                                                    let target = buffers.len() - 1;
+                                                    switch(
+                                                        &mut buffers,
+                                                        &mut current,
+                                                        target,
+                                                        &mut canvas,
+                                                        &mut file_name,
+                                                    );
+                                                    terminal.clear()?;
+                                                }
+                                                Ok(Outcome::Continue(Some(message))) => {
+                                                    status_bar.notify(&message)
+                                                }
+                                                Ok(Outcome::Continue(None)) => {}
+                                                Err(error) => status_bar.notify(&error.to_string()),
+                                            }
+                                            None
+                                        }
+                                        Query::Overwrite(_)
+                                        | Query::Quit
+                                        | Query::Restore(_)
+                                        | Query::Text(_)
+                                        | Query::BrushChar => None,
                                     };
 
-                                    toolbar.highlight_tool(tool);
+                                    if let Some(name) = target {
+                                        match save(&mut canvas, Some(&name), &opts) {
+                                            Ok(_) => {
+                                                status_bar.notify(&format!("Saved to {}", name));
+                                                file_name = Some(name);
+                                            }
+                                            Err(error) => status_bar
+                                                .notify(&format!("Couldn't save: {}", error)),
+                                        }
+                                    }
+                                }
+                                (_, Key::Tab) => {
+                                    match query {
+                                        Query::SaveAs => input.complete_path(&opts.config.save_dir),
+                                        Query::Command(_) => {
+                                            let base = &opts.config.save_dir;
+                                            if let Some(line) =
+                                                command::complete(input.value(), base)
+                                            {
+                                                input.set_value(&line);
+                                            }
+                                        }
+                                        _ => {}
+                                    }
+                                    prompt = Some((query, input));
+                                }
+                                (Query::Command(position), Key::Up) if *position > 0 => {
+                                    input.set_value(&history[position - 1]);
+                                    prompt = Some((Query::Command(position - 1), input));
+                                }
+                                (Query::Command(position), Key::Down)
+                                    if *position < history.len() =>
+                                {
+                                    let position = position + 1;
+                                    input.set_value(
+                                        history.get(position).map_or("", String::as_str),
+                                    );
+                                    prompt = Some((Query::Command(position), input));
+                                }
+                                (_, Key::Backspace) => {
+                                    input.pop();
+                                    prompt = Some((query, input));
+                                }
+                                (_, Key::Char(char)) => {
+                                    input.push(char);
+                                    prompt = Some((query, input));
+                                }
+                                (_, Key::Up) | (_, Key::Down) | (_, Key::F(_)) => {
+                                    prompt = Some((query, input))
                                 }
-                                _ => {}
+                            }
+                        }
+                        terminal::Event::Key(event)
+                            if help.is_some()
+                                && (event.key == Key::Esc
+                                    || keymap.action(event) == Some(Action::Help)) =>
+                        {
+                            let overlay = help.take().expect("help is open");
+                            screen.erase(&mut overlay.segment())?;
+                        }
+                        terminal::Event::Key(event)
+                            if minimap.is_some()
+                                && (event.key == Key::Esc
+                                    || keymap.action(event) == Some(Action::Minimap)) =>
+                        {
+                            let overlay = minimap.take().expect("minimap is open");
+                            screen.erase(&mut overlay.segment())?;
+                        }
+                        terminal::Event::Key(terminal::KeyEvent { key: Key::Tab, .. })
+                            if help.is_some() =>
+                        {
+                            let overlay = help.as_mut().expect("help is open");
+                            screen.erase(&mut overlay.segment())?;
+                            overlay.next_page();
+                        }
+                        // The overlay stays up until dismissed
+                        terminal::Event::Key(_) | terminal::Event::Mouse(_) if help.is_some() => {}
+                        terminal::Event::Key(event) => action = keymap.action(event),
+                        terminal::Event::Mouse(event) if event.pos.1 <= menu::ToolBar::HEIGHT => {
+                            let pos = event.pos.into();
+                            match event.action {
+                                terminal::MouseAction::Press => {
+                                    pressed = toolbar.action_at(pos);
+                                    toolbar.hover(Some(pos));
+                                }
+                                terminal::MouseAction::Drag => {
+                                    toolbar.hover(pressed.and(Some(pos)));
+                                }
+                                terminal::MouseAction::Release => {
+                                    // Like a button, the click only counts if it ends on the
+                                    // label it started on
+                                    if prompt.is_none() && toolbar.action_at(pos) == pressed {
+                                        action = pressed;
+                                    }
+                                    pressed = None;
+                                    toolbar.hover(None);
+                                }
+                            }
+                        }
+                        // Clicking the minimap jumps there, and nothing is drawn under it up to the
+                        // next press
+                        terminal::Event::Mouse(event)
+                            if matches!(event.action, terminal::MouseAction::Press)
+                                && minimap
+                                    .as_ref()
+                                    .is_some_and(|overlay| overlay.contains(event.pos.into())) =>
+                        {
+                            refused = true;
+                            let overlay = minimap.as_ref().expect("minimap is open");
+                            if let Some(pos) = overlay.block_at(event.pos.into()) {
+                                let viewport = canvas.viewport(canvas_area);
+                                canvas.scroll_to(viewport.centred_on(pos));
+                                terminal.clear()?;
                             }
                         }
                         // Reserve toolbar space
-                        terminal::Event::Mouse(event) if event.pos.1 > 3 => {
-                            match (event.action, event.pos) {
+                        terminal::Event::Mouse(event)
+                            if event.pos.1 > menu::ToolBar::HEIGHT && event.pos.1 < height =>
+                        {
+                            let snapping = snap
+                                && resolution == canvas::Resolution::Cell
+                                && tool.shape(canvas.cursor, canvas.cursor).is_some();
+                            if snapping && matches!(event.action, terminal::MouseAction::Press) {
+                                alignment = canvas.alignment();
+                            }
+                            let viewport = canvas.viewport(canvas_area);
+                            let pos = match viewport.to_canvas(event.pos.into()) {
+                                pos if snapping => canvas::snap(
+                                    pos,
+                                    page_origin(),
+                                    viewport.bounds(),
+                                    opts.config.snap_spacing,
+                                    &alignment,
+                                ),
+                                pos => pos,
+                            };
+                            status_bar.set_cursor(pos);
+                            let layer = canvas.active_layer();
+                            let drawing = !matches!(tool, canvas::Tool::Pick | canvas::Tool::Edit);
+                            match (event.action, (pos.x, pos.y)) {
+                                (terminal::MouseAction::Press, _)
+                                    if drawing && (layer.locked || !layer.visible) =>
+                                {
+                                    refused = true;
+                                    let reason = if layer.locked { "locked" } else { "hidden" };
+                                    status_bar.notify(&format!(
+                                        "{} is {}, so nothing can be drawn on it",
+                                        layer.name, reason
+                                    ));
+                                }
+                                (terminal::MouseAction::Drag, _) if refused => {}
+                                (terminal::MouseAction::Release, _) if refused => refused = false,
                                 (terminal::MouseAction::Press, (x, y)) => {
-                                    canvas.cursor.move_to(x, y)
+                                    refused = false;
+                                    canvas.cursor.move_to(x, y);
+                                    if !canvas.on_page((x, y).into()) {
+                                        status_bar.notify("Off the page, so this will be clipped");
+                                    }
+                                    match tool {
+                                        canvas::Tool::Pick => {
+                                            if let Some(picked) = canvas.format_at((x, y).into()) {
+                                                format = picked;
+                                                toolbar.highlight_format(format);
+                                            }
+                                        }
+                                        canvas::Tool::Edit => {
+                                            canvas.select_at((x, y).into());
+                                        }
+                                        canvas::Tool::Erase => {}
+                                        canvas::Tool::Brush => {
+                                            sketch.set_format(format);
+                                            brush.stamp((x, y).into(), &mut sketch);
+                                        }
+                                        _ => sketch.set_format(format),
+                                    }
                                 }
                                 (terminal::MouseAction::Drag, (x, y)) => match tool {
-                                    canvas::Tool::Plot => {
-                                        sketch += tracer.trace(canvas.cursor, (x, y).into());
+                                    canvas::Tool::Plot
+                                        if resolution == canvas::Resolution::Braille =>
+                                    {
+                                        canvas.tracer.trace_braille(
+                                            canvas.cursor,
+                                            (x, y).into(),
+                                            &mut dots,
+                                        );
+                                        sketch = dots.to_segment(format);
+                                        canvas.cursor.move_to(x, y);
+                                    }
+                                    canvas::Tool::Line
+                                        if resolution == canvas::Resolution::Braille =>
+                                    {
+                                        screen.erase(&mut viewport.show(&sketch))?;
+                                        dots.clear();
+                                        canvas.tracer.trace_braille(
+                                            canvas.cursor,
+                                            (x, y).into(),
+                                            &mut dots,
+                                        );
+                                        sketch = dots.to_segment(format);
+                                    }
+                                    canvas::Tool::Plot
+                                        if resolution == canvas::Resolution::Pixel =>
+                                    {
+                                        canvas.tracer.trace_pixels(
+                                            canvas.cursor,
+                                            (x, y).into(),
+                                            pixel_color(format),
+                                            &mut pixels,
+                                        );
+                                        sketch = pixels.to_segments().iter().sum();
                                         canvas.cursor.move_to(x, y);
                                     }
-                                    canvas::Tool::Line => {
-                                        screen.erase(&mut sketch)?;
-                                        sketch = tracer.trace(canvas.cursor, (x, y).into());
+                                    canvas::Tool::Line
+                                        if resolution == canvas::Resolution::Pixel =>
+                                    {
+                                        screen.erase(&mut viewport.show(&sketch))?;
+                                        pixels.clear();
+                                        canvas.tracer.trace_pixels(
+                                            canvas.cursor,
+                                            (x, y).into(),
+                                            pixel_color(format),
+                                            &mut pixels,
+                                        );
+                                        sketch = pixels.to_segments().iter().sum();
+                                    }
+                                    canvas::Tool::Plot => {
+                                        sketch += canvas.tracer.trace(canvas.cursor, (x, y).into());
+                                        canvas.cursor.move_to(x, y);
                                     }
                                     canvas::Tool::Erase => {
                                         sketch.add(grid::Cell::new((x, y).into(), ' '));
                                     }
+                                    canvas::Tool::Brush => {
+                                        let path =
+                                            canvas.tracer.trace(canvas.cursor, (x, y).into());
+                                        for cell in path.cells() {
+                                            brush.stamp(cell.pos(), &mut sketch);
+                                        }
+                                        canvas.cursor.move_to(x, y);
+                                    }
+                                    canvas::Tool::Edit => {
+                                        if let Some(previous) = canvas.drag_selection((x, y).into())
+                                        {
+                                            screen.erase(&mut viewport.show(&previous))?;
+                                        }
+                                    }
+                                    canvas::Tool::Pick => {}
+                                    _ => {
+                                        screen.erase(&mut viewport.show(&sketch))?;
+                                        if let Some(shape) =
+                                            tool.shape(canvas.cursor, (x, y).into())
+                                        {
+                                            sketch = shape.rasterize(&canvas.tracer);
+                                            sketch.set_format(format);
+                                        }
+                                        if snapping {
+                                            screen.erase(&mut viewport.show(&alignment_print))?;
+                                            let (from, to) = (canvas.cursor, pos);
+                                            let centre = grid::Point::new(
+                                                (from.x + to.x) / 2,
+                                                (from.y + to.y) / 2,
+                                            );
+                                            alignment_print = menu::alignment_guides(
+                                                &[from, to, centre],
+                                                &alignment,
+                                                viewport.bounds(),
+                                                &theme,
+                                            );
+                                        }
+                                    }
                                 },
-                                (terminal::MouseAction::Release, _) => {
-                                    canvas.add(sketch.clone());
-                                    sketch.clear();
+                                (terminal::MouseAction::Release, (x, y)) => {
+                                    if !sketch.is_empty() {
+                                        let shape = match tool {
+                                            canvas::Tool::Line
+                                                if resolution != canvas::Resolution::Cell =>
+                                            {
+                                                None
+                                            }
+                                            _ => tool.shape(canvas.cursor, (x, y).into()),
+                                        };
+                                        match shape {
+                                            Some(shape @ canvas::Shape::TextBox(..)) => {
+                                                screen.erase(&mut viewport.show(&sketch))?;
+                                                prompt = Some((
+                                                    Query::Text(shape),
+                                                    new_prompt("Text: ", theme),
+                                                ));
+                                            }
+                                            Some(shape) => canvas.add_shape(shape, format),
+                                            None if !pixels.is_empty() => {
+                                                canvas.add_pixels(pixels.to_segments())
+                                            }
+                                            None => canvas.add(sketch.clone()),
+                                        }
+                                    }
+                                    sketch = grid::Segment::new();
+                                    dots.clear();
+                                    pixels.clear();
+                                    screen.erase(&mut viewport.show(&alignment_print))?;
+                                    alignment_print = grid::Segment::new();
                                 }
                             }
                         }
                         _ => {}
                     }
+
+                    match action {
+                        Some(Action::Quit)
+                            if canvas.is_dirty()
+                                || buffers.iter().enumerate().any(|(index, buffer)| {
+                                    index != current && buffer.canvas.is_dirty()
+                                }) =>
+                        {
+                            prompt = Some((
+                                Query::Quit,
+                                new_prompt(
+                                    "Unsaved changes. (s)ave, (d)iscard or (c)ancel? ",
+                                    theme,
+                                ),
+                            ));
+                        }
+                        Some(Action::Quit) => break,
+                        Some(Action::Undo) => {
+                            if let Some(segment) = canvas.undo() {
+                                screen.erase(&mut canvas.viewport(canvas_area).show(&segment))?;
+                            }
+                        }
+                        Some(Action::Clear) => {
+                            canvas.clear();
+                            sketch.clear();
+                            terminal.clear()?;
+                        }
+                        Some(Action::Save) => {
+                            match save(&mut canvas, file_name.as_deref(), &opts) {
+                                Ok(name) => {
+                                    status_bar.notify(&format!("Saved to {}", name));
+                                    file_name = Some(name);
+                                }
+                                Err(error) => {
+                                    status_bar.notify(&format!("Couldn't save: {}", error))
+                                }
+                            }
+                        }
+                        Some(Action::SaveAs) => {
+                            prompt = Some((Query::SaveAs, new_prompt("Save as: ", theme)));
+                        }
+                        Some(Action::Copy) => {
+                            let (blueprint, message) = match canvas.selected_segment() {
+                                Some(selected) => (selected, "Copied selection to clipboard"),
+                                None => (
+                                    canvas.snapshot(opts.visible_only).iter().sum(),
+                                    "Copied to clipboard",
+                                ),
+                            };
+                            terminal.copy_to_clipboard(&String::from(blueprint))?;
+                            status_bar.notify(message);
+                        }
+                        Some(Action::DeleteSelection) if tool == canvas::Tool::Edit => {
+                            if let Some(segment) = canvas.delete_selection() {
+                                screen.erase(&mut canvas.viewport(canvas_area).show(&segment))?;
+                            }
+                        }
+                        Some(Action::BrushChar) => {
+                            prompt = Some((
+                                Query::BrushChar,
+                                new_prompt("Press a key to paint with: ", theme),
+                            ));
+                        }
+                        Some(Action::ToggleBraille) => {
+                            resolution = match resolution {
+                                canvas::Resolution::Braille => canvas::Resolution::Cell,
+                                _ => canvas::Resolution::Braille,
+                            };
+                            toolbar.set_resolution(resolution);
+                        }
+                        Some(Action::TogglePixels) => {
+                            resolution = match resolution {
+                                canvas::Resolution::Pixel => canvas::Resolution::Cell,
+                                _ => canvas::Resolution::Pixel,
+                            };
+                            toolbar.set_resolution(resolution);
+                        }
+                        Some(Action::BrushSize) => {
+                            brush.size = brush.size.next();
+                            toolbar.set_brush(brush);
+                        }
+                        Some(Action::NewLayer) => {
+                            prompt = Some((Query::NewLayer, new_prompt("Layer name: ", theme)));
+                        }
+                        Some(Action::PreviousLayer) => canvas.previous_layer(),
+                        Some(Action::NextLayer) => canvas.next_layer(),
+                        Some(Action::LowerLayer) => canvas.lower_layer(),
+                        Some(Action::RaiseLayer) => canvas.raise_layer(),
+                        Some(Action::ToggleVisibility) => {
+                            canvas.deselect();
+                            let layer = canvas.active_layer_mut();
+                            layer.visible = !layer.visible;
+                            terminal.clear()?;
+                        }
+                        Some(Action::ToggleLock) => {
+                            canvas.deselect();
+                            let layer = canvas.active_layer_mut();
+                            layer.locked = !layer.locked;
+                        }
+                        Some(Action::NextFgColor) => {
+                            format.fg_color = menu::next_color(format.fg_color);
+                            toolbar.highlight_format(format);
+                        }
+                        Some(Action::NextBgColor) => {
+                            format.bg_color = menu::next_color(format.bg_color);
+                            toolbar.highlight_format(format);
+                        }
+                        Some(Action::ToggleAttribute(attribute)) => {
+                            format.attributes.toggle(attribute);
+                            toolbar.highlight_format(format);
+                        }
+                        Some(Action::CycleTheme) => {
+                            theme_index = (theme_index + 1) % themes.len();
+                            theme = themes[theme_index].1;
+                            toolbar = menu::ToolBar::new(&keymap, theme, width);
+                            toolbar.highlight_tool(tool);
+                            toolbar.highlight_format(format);
+                            toolbar.set_brush(brush);
+                            toolbar.set_resolution(resolution);
+                            status_bar.set_theme(theme);
+                            status_bar.notify(&format!("Theme: {}", themes[theme_index].0));
+                            terminal.clear()?;
+                        }
+                        Some(Action::CommandLine) => {
+                            prompt = Some((Query::Command(history.len()), new_prompt(":", theme)));
+                        }
+                        Some(Action::NewBuffer) => {
+                            buffers.push(Buffer { canvas: new_canvas(&opts), file_name: None });
+                            let target = buffers.len() - 1;
+                            switch(&mut buffers, &mut current, target, &mut canvas, &mut file_name);
+                            sketch.clear();
+                            terminal.clear()?;
+                        }
+                        Some(action @ Action::PreviousBuffer)
+                        | Some(action @ Action::NextBuffer) => {
+                            let count = buffers.len();
+                            let target = match action {
+                                Action::PreviousBuffer => (current + count - 1) % count,
+                                _ => (current + 1) % count,
+                            };
+                            switch(&mut buffers, &mut current, target, &mut canvas, &mut file_name);
+                            sketch.clear();
+                            terminal.clear()?;
+                        }
+                        Some(Action::CopySelection) => match canvas.copy_selection() {
+                            Some(copied) => {
+                                clip = Some(copied);
+                                status_bar.notify("Copied selection");
+                            }
+                            None => status_bar.notify("Select something with the edit tool first"),
+                        },
+                        Some(Action::Paste) => {
+                            if let Some(ref copied) = clip {
+                                canvas.paste(copied);
+                                // The pasted copy is selected, ready to be dragged into place
+                                tool = canvas::Tool::Edit;
+                                toolbar.highlight_tool(tool);
+                            }
+                        }
+                        Some(Action::ToggleSnap) => {
+                            snap = !snap;
+                            status_bar.set_snap(snap);
+                        }
+                        Some(Action::Help) => {
+                            help = Some(menu::Help::new(&keymap, theme, (width, height)));
+                        }
+                        Some(Action::Minimap) => {
+                            minimap = Some(menu::Minimap::new(
+                                &canvas.snapshot(false),
+                                canvas.viewport(canvas_area).bounds(),
+                                theme,
+                                (width, height),
+                            ));
+                        }
+                        Some(Action::SelectTool(selected)) => {
+                            tool = selected;
+                            toolbar.highlight_tool(tool);
+                        }
+                        _ => {}
+                    }
+                }
+
+                let autosave_due = opts
+                    .config
+                    .autosave_interval()
+                    .is_some_and(|interval| last_autosave.elapsed() >= interval)
+                    // Until answered, the sketches to restore are only in the prompt
+                    && !matches!(prompt, Some((Query::Restore(_), _)));
+                let sketches = open_sketches(&buffers, current, &canvas, file_name.as_deref());
+                if autosave_due && sketches.iter().any(|(sketch, _)| sketch.is_dirty()) {
+                    let dirty = sketches
+                        .iter()
+                        .filter(|(sketch, _)| sketch.is_dirty())
+                        .map(|(sketch, name)| recovery::Sketch {
+                            file_name: name.map(String::from),
+                            canvas: sketch.record(),
+                        })
+                        .collect();
+                    // A failed autosave shouldn't interrupt drawing
+                    let _ = recovery.save(dirty);
+                    last_autosave = time::Instant::now();
                 }
 
-                write!(screen, "{}{}{}{}", canvas, sketch, toolbar, file_name_print)?;
+                toolbar.set_modified(canvas.is_dirty());
+                let tabs: Vec<(String, bool)> = sketches
+                    .iter()
+                    .map(|(sketch, name)| (buffer_name(*name), sketch.is_dirty()))
+                    .collect();
+                toolbar.set_buffers(&tabs, current);
+                toolbar.set_layer(canvas.active_layer(), canvas.layer_position());
+                let viewport = canvas.viewport(canvas_area);
+                screen.erase(&mut viewport.show(&handles_print))?;
+                status_bar.set_tool(tool, brush);
+                status_bar.set_selection(canvas.selection_size().or_else(|| {
+                    let (start, end) = sketch.boundaries()?;
+                    Some((end.x - start.x + 1, end.y - start.y + 1))
+                }));
+                status_bar.set_file(file_name.as_deref(), canvas.is_dirty());
+                // The guides are drawn from the canvas origin, so that they scroll with it
+                let area = (page_origin(), viewport.bounds().1);
+                for segment in menu::page_frame(canvas.page_bounds(), area, guides, &theme) {
+                    write!(screen, "{}", viewport.show(&segment))?;
+                }
+                write!(screen, "{}", viewport.show(&alignment_print))?;
+                for segment in canvas.snapshot(true).iter().chain(Some(&sketch)) {
+                    write!(screen, "{}", viewport.show(segment))?;
+                }
+                write!(screen, "{}{}", toolbar, status_bar)?;
+                for segment in pixels.to_segments() {
+                    write!(screen, "{}", viewport.show(&segment))?;
+                }
+                if tool == canvas::Tool::Edit {
+                    handles_print = grid::Segment::new();
+                    handles_print.set_format(theme.selection.format());
+                    canvas
+                        .selection_handles()
+                        .into_iter()
+                        .for_each(|pos| handles_print.add(grid::Cell::new(pos, '+')));
+                    write!(screen, "{}", viewport.show(&handles_print))?;
+                }
+                // Rebuilt every frame to follow the sketch as it changes
+                if let Some(previous) = minimap.take() {
+                    let overlay = menu::Minimap::new(
+                        &canvas.snapshot(false),
+                        viewport.bounds(),
+                        theme,
+                        (width, height),
+                    );
+                    if previous.segment().cells().len() != overlay.segment().cells().len() {
+                        screen.erase(&mut previous.segment())?;
+                    }
+                    write!(screen, "{}", overlay)?;
+                    minimap = Some(overlay);
+                }
+                if let Some(ref overlay) = help {
+                    write!(screen, "{}", overlay)?;
+                }
+                if let Some((_, ref input)) = prompt {
+                    write!(screen, "{}", input)?;
+                }
                 screen.flush()?;
             }
             Err(terminal::InputError::UnknownError(error)) => return Err(error.into()),
@@ -139,5 +894,204 @@ fn run_canvas(terminal: &mut terminal::Terminal, char_set: grid::CharSet) -> cra
         }
     }
 
-    Ok(())
+    recovery.discard()
+}
+
+fn save(
+    canvas: &mut canvas::Canvas,
+    file_name: Option<&str>,
+    opts: &Opts,
+) -> crate::Result<String> {
+    let snapshot = canvas.snapshot(opts.visible_only);
+    let name = match file_name {
+        Some(name) => {
+            export::to_file_as(snapshot, name, &opts.export_mode)?;
+            name.to_string()
+        }
+        None => export::to_file(
+            snapshot,
+            &opts.export_mode,
+            &opts.config.save_dir,
+            &opts.config.file_name,
+        )?,
+    };
+
+    canvas.mark_saved();
+    Ok(name)
+}
+
+fn run_command(
+    command: command::Command,
+    canvas: &mut canvas::Canvas,
+    file_name: &mut Option<String>,
+    guides: &mut u16,
+    terminal: &mut terminal::Terminal,
+    opts: &Opts,
+) -> crate::Result<Outcome> {
+    let path_name = |path: &str| opts.config.save_dir.join(path).to_string_lossy().into_owned();
+    let message = match command {
+        command::Command::Write { path, force } => {
+            let target = path.as_deref().map(path_name);
+            if let Some(ref name) = target {
+                let exists = path::Path::new(name).exists();
+                if exists && !force && file_name.as_ref() != Some(name) {
+                    return Err(format!("{} already exists, use :w! to overwrite it", name).into());
+                }
+            }
+            let name = save(canvas, target.as_deref().or(file_name.as_deref()), opts)?;
+            let message = format!("Saved to {}", name);
+            *file_name = Some(name);
+            Some(message)
+        }
+        command::Command::Edit { path, force } => {
+            if canvas.is_dirty() && !force {
+                return Err("unsaved changes, use :e! to discard them".into());
+            }
+            let name = path_name(&path);
+            let content =
+                fs::read_to_string(&name).map_err(|error| format!("{}: {}", name, error))?;
+            // Start over with a fresh history, keeping the charset and page
+            let page = canvas.page();
+            *canvas = canvas::Canvas::new(mem::take(&mut canvas.tracer));
+            canvas.set_page(page_origin(), page);
+            canvas.add(export::from_text(page_origin(), &content));
+            canvas.mark_saved();
+            terminal.clear()?;
+            let message = format!("Opened {}", name);
+            *file_name = Some(name);
+            Some(message)
+        }
+        command::Command::Export { mode, path } => {
+            let name = path_name(&path);
+            export::to_file_as(canvas.snapshot(opts.visible_only), &name, &mode)?;
+            Some(format!("Exported to {}", name))
+        }
+        command::Command::Charset(charset) => {
+            canvas.tracer = grid::Tracer::new(charset);
+            terminal.clear()?;
+            None
+        }
+        command::Command::New(path) => {
+            let buffer = match path {
+                Some(path) => open(&path_name(&path), opts)?,
+                None => Buffer { canvas: new_canvas(opts), file_name: None },
+            };
+            return Ok(Outcome::Open(buffer));
+        }
+        command::Command::NewLayer(name) => {
+            canvas.add_layer(&name);
+            None
+        }
+        command::Command::Resize(page) => {
+            canvas.set_page(page_origin(), page);
+            terminal.clear()?;
+            None
+        }
+        command::Command::Guides(cells) => {
+            *guides = cells;
+            terminal.clear()?;
+            None
+        }
+        command::Command::Quit { force } => {
+            if canvas.is_dirty() && !force {
+                return Err("unsaved changes, use :q! to discard them or :wq to save them".into());
+            }
+            return Ok(Outcome::Quit);
+        }
+        command::Command::WriteQuit => {
+            save(canvas, file_name.as_deref(), opts)?;
+            return Ok(Outcome::Quit);
+        }
+    };
+    Ok(Outcome::Continue(message))
+}
+
+fn new_canvas(opts: &Opts) -> canvas::Canvas {
+    let mut canvas = canvas::Canvas::new(grid::Tracer::new(opts.config.charset.clone().into()));
+    canvas.set_page(page_origin(), opts.config.page);
+    canvas
+}
+
+/// Opens the text file `name` in a new buffer, which starts out blank if there's no such file
+/// yet.
+fn open(name: &str, opts: &Opts) -> crate::Result<Buffer> {
+    let mut canvas = new_canvas(opts);
+    match fs::read_to_string(name) {
+        Ok(content) => {
+            canvas.add(export::from_text(page_origin(), &content));
+            canvas.mark_saved();
+        }
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(format!("{}: {}", name, error).into()),
+    }
+    Ok(Buffer { canvas, file_name: Some(name.to_string()) })
+}
+
+/// Swaps the sketch being drawn on back into its buffer, and the one at `target` out.
+fn switch(
+    buffers: &mut [Buffer],
+    current: &mut usize,
+    target: usize,
+    canvas: &mut canvas::Canvas,
+    file_name: &mut Option<String>,
+) {
+    for index in [*current, target] {
+        mem::swap(&mut buffers[index].canvas, canvas);
+        mem::swap(&mut buffers[index].file_name, file_name);
+    }
+    *current = target;
+}
+
+/// Drops the sketch being drawn on and swaps in the one after it, or the one before when it
+/// was the last.
+fn close(
+    buffers: &mut Vec<Buffer>,
+    current: &mut usize,
+    canvas: &mut canvas::Canvas,
+    file_name: &mut Option<String>,
+) {
+    buffers.remove(*current);
+    *current = (*current).min(buffers.len() - 1);
+    mem::swap(&mut buffers[*current].canvas, canvas);
+    mem::swap(&mut buffers[*current].file_name, file_name);
+}
+
+/// Every open sketch and its file name, in tab order, with the one being drawn on in place of
+/// the blank left in its buffer.
+fn open_sketches<'a>(
+    buffers: &'a [Buffer],
+    current: usize,
+    canvas: &'a canvas::Canvas,
+    file_name: Option<&'a str>,
+) -> Vec<(&'a canvas::Canvas, Option<&'a str>)> {
+    buffers
+        .iter()
+        .enumerate()
+        .map(|(index, buffer)| match index == current {
+            true => (canvas, file_name),
+            false => (&buffer.canvas, buffer.file_name.as_deref()),
+        })
+        .collect()
+}
+
+fn buffer_name(file_name: Option<&str>) -> String {
+    file_name
+        .map(|name| {
+            path::Path::new(name).file_name().map_or(name.into(), |name| name.to_string_lossy())
+        })
+        .map_or_else(|| "[new]".to_string(), |name| name.into_owned())
+}
+
+/// The top left cell of the canvas, just under the toolbar.
+fn page_origin() -> grid::Point {
+    grid::Point::new(1, menu::ToolBar::HEIGHT + 1)
+}
+
+/// Colour to paint pixels with in pixel mode; the default foreground can't be told apart from
+/// an unpainted pixel once drawn, so white is used instead.
+fn pixel_color(format: terminal::Format) -> terminal::Color {
+    match format.fg_color {
+        terminal::Color::Reset => terminal::Color::White,
+        color => color,
+    }
 }
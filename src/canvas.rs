@@ -1,50 +1,1264 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
+use std::str;
 
+use serde::{Deserialize, Serialize};
 use terminal::grid;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+use crate::config;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum Tool {
+    #[default]
     Plot,
     Line,
     Erase,
+    Pick,
+    Rectangle,
+    Arrow,
+    Text,
+    Edit,
+    Ellipse,
+    Brush,
+}
+
+#[rustfmt::skip]
+static TOOL_NAMES: [(&str, Tool); 10] = [
+    ("plot", Tool::Plot),
+    ("line", Tool::Line),
+    ("erase", Tool::Erase),
+    ("pick", Tool::Pick),
+    ("rectangle", Tool::Rectangle),
+    ("arrow", Tool::Arrow),
+    ("text", Tool::Text),
+    ("edit", Tool::Edit),
+    ("ellipse", Tool::Ellipse),
+    ("brush", Tool::Brush),
+];
+
+impl Tool {
+    /// Every tool, in toolbar order.
+    pub fn all() -> impl Iterator<Item = Tool> {
+        TOOL_NAMES.iter().map(|(_, tool)| *tool)
+    }
+
+    /// The shape this tool draws when dragged from `from` to `to`, if it draws shapes.
+    pub fn shape(self, from: grid::Point, to: grid::Point) -> Option<Shape> {
+        match self {
+            Tool::Line => Some(Shape::Line(from, to)),
+            Tool::Rectangle => Some(Shape::Rectangle(from, to)),
+            Tool::Arrow => Some(Shape::Arrow(from, to)),
+            Tool::Text => Some(Shape::TextBox(from, to, String::new())),
+            Tool::Ellipse => Some(Shape::Ellipse(from, to)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Tool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = TOOL_NAMES.iter().find(|(_, tool)| tool == self).expect("unnamed tool");
+        write!(f, "{}", name)
+    }
+}
+
+impl str::FromStr for Tool {
+    type Err = crate::Error;
+
+    fn from_str(name: &str) -> crate::Result<Self> {
+        TOOL_NAMES.iter().find(|(n, _)| *n == name).map(|(_, tool)| *tool).ok_or_else(|| {
+            let names: Vec<&str> = TOOL_NAMES.iter().map(|(name, _)| *name).collect();
+            format!("unknown tool `{}`, expected one of: {}", name, names.join(", ")).into()
+        })
+    }
+}
+
+impl TryFrom<String> for Tool {
+    type Error = crate::Error;
+
+    fn try_from(name: String) -> crate::Result<Self> {
+        name.parse()
+    }
+}
+
+impl From<Tool> for String {
+    fn from(tool: Tool) -> Self {
+        tool.to_string()
+    }
+}
+
+/// A fixed area to sketch in, written like `80x24`, or just `72` to only limit the width.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Page {
+    pub width: u16,
+    pub height: Option<u16>,
+}
+
+impl Page {
+    /// The bottom right cell of the page when its top left cell is at `origin`.
+    pub fn end(self, origin: grid::Point) -> grid::Point {
+        grid::Point::new(
+            origin.x.saturating_add(self.width - 1),
+            self.height.map_or(u16::MAX, |height| origin.y.saturating_add(height - 1)),
+        )
+    }
+}
+
+impl fmt::Display for Page {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.height {
+            Some(height) => write!(f, "{}x{}", self.width, height),
+            None => write!(f, "{}", self.width),
+        }
+    }
+}
+
+impl str::FromStr for Page {
+    type Err = crate::Error;
+
+    fn from_str(size: &str) -> crate::Result<Self> {
+        let invalid = || format!("invalid page size `{}`, expected one like 80x24 or 72", size);
+        let dimension = |value: &str| value.parse().ok().filter(|value| *value > 0);
+        let page = match size.split_once('x') {
+            Some((width, height)) => match (dimension(width), dimension(height)) {
+                (Some(width), Some(height)) => Page { width, height: Some(height) },
+                _ => return Err(invalid().into()),
+            },
+            None => Page { width: dimension(size).ok_or_else(invalid)?, height: None },
+        };
+        Ok(page)
+    }
+}
+
+impl TryFrom<String> for Page {
+    type Error = crate::Error;
+
+    fn try_from(size: String) -> crate::Result<Self> {
+        size.parse()
+    }
+}
+
+impl From<Page> for String {
+    fn from(page: Page) -> Self {
+        page.to_string()
+    }
+}
+
+/// Moves `pos` onto a column or row from `alignment` when it's within a cell of one, and
+/// otherwise onto the nearest line of a grid every `spacing` cells from `origin`, staying
+/// within `area`.
+pub fn snap(
+    pos: grid::Point,
+    origin: grid::Point,
+    area: (grid::Point, grid::Point),
+    spacing: u16,
+    (columns, rows): &(Vec<u16>, Vec<u16>),
+) -> grid::Point {
+    let axis = |value: u16, origin: u16, lines: &[u16], (low, high): (u16, u16)| {
+        let aligned = lines.iter().copied().min_by_key(|line| line.abs_diff(value));
+        let snapped = match aligned {
+            Some(line) if line.abs_diff(value) <= 1 => line,
+            _ if spacing == 0 => value,
+            _ => {
+                let offset = value.saturating_sub(origin);
+                let line = origin.saturating_add((offset + spacing / 2) / spacing * spacing);
+                // Rather the line before than one off the edge
+                if line > high && line - origin >= spacing {
+                    line - spacing
+                } else {
+                    line
+                }
+            }
+        };
+        snapped.clamp(low, high)
+    };
+    let (start, end) = area;
+    grid::Point::new(
+        axis(pos.x, origin.x, columns, (start.x, end.x)),
+        axis(pos.y, origin.y, rows, (start.y, end.y)),
+    )
+}
+
+/// The part of a canvas on screen: the canvas area, scrolled right and down by `offset` cells.
+#[derive(Debug, Copy, Clone)]
+pub struct Viewport {
+    area: (grid::Point, grid::Point),
+    offset: grid::Point,
+}
+
+impl Viewport {
+    /// The canvas cell under `pos` on screen.
+    pub fn to_canvas(self, pos: grid::Point) -> grid::Point {
+        grid::Point::new(pos.x.saturating_add(self.offset.x), pos.y.saturating_add(self.offset.y))
+    }
+
+    /// The top left and bottom right canvas cells on screen.
+    pub fn bounds(self) -> (grid::Point, grid::Point) {
+        (self.to_canvas(self.area.0), self.to_canvas(self.area.1))
+    }
+
+    /// `segment` moved to where it shows on screen, without whatever's scrolled out of view.
+    pub fn show(self, segment: &grid::Segment) -> grid::Segment {
+        let (start, end) = self.bounds();
+        let in_view = |pos: grid::Point| {
+            (start.x..=end.x).contains(&pos.x) && (start.y..=end.y).contains(&pos.y)
+        };
+        let mut shown = grid::Segment::new();
+        shown.set_format(segment.format());
+        for cell in segment.cells() {
+            if in_view(cell.pos()) && cell.partner().is_none_or(in_view) {
+                shown.add(cell.clone());
+            }
+        }
+        shown.translate(-i32::from(self.offset.x), -i32::from(self.offset.y));
+        shown
+    }
+
+    /// The offset that puts `pos` in the middle of the screen, or as close as the top and left
+    /// edges of the canvas allow.
+    pub fn centred_on(self, pos: grid::Point) -> grid::Point {
+        let (start, end) = self.area;
+        grid::Point::new(
+            pos.x.saturating_sub(start.x + (end.x - start.x) / 2),
+            pos.y.saturating_sub(start.y + (end.y - start.y) / 2),
+        )
+    }
+}
+
+/// How finely the plot and line tools draw within each cell.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Resolution {
+    #[default]
+    Cell,
+    Braille,
+    Pixel,
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum BrushSize {
+    #[default]
+    Single,
+    Square,
+    Circle,
+}
+
+impl BrushSize {
+    pub fn next(self) -> Self {
+        match self {
+            BrushSize::Single => BrushSize::Square,
+            BrushSize::Square => BrushSize::Circle,
+            BrushSize::Circle => BrushSize::Single,
+        }
+    }
+
+    /// Cell offsets covered by the brush around its centre. The circle is twice as wide as
+    /// it is tall, to make up for terminal cells being roughly twice as tall as they're wide.
+    fn offsets(self) -> Vec<(i32, i32)> {
+        match self {
+            BrushSize::Single => vec![(0, 0)],
+            BrushSize::Square => (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))).collect(),
+            BrushSize::Circle => (-2..=2)
+                .flat_map(|dy| (-3..=3).map(move |dx| (dx, dy)))
+                .filter(|(dx, dy)| 4 * dx * dx + 9 * dy * dy <= 36)
+                .collect(),
+        }
+    }
 }
 
-impl Default for Tool {
+impl fmt::Display for BrushSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrushSize::Single => write!(f, "1x1"),
+            BrushSize::Square => write!(f, "3x3"),
+            BrushSize::Circle => write!(f, "circle"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Brush {
+    pub char: char,
+    pub size: BrushSize,
+}
+
+impl Brush {
+    /// Paints the brush footprint centred on `pos` into `segment`, skipping cells it already
+    /// covers. A wide character takes two columns, so the footprint is spread out to match.
+    pub fn stamp(&self, pos: grid::Point, segment: &mut grid::Segment) {
+        let glyph = self.char.to_string();
+        let width = grid::text_width(&glyph) as u16;
+        for (dx, dy) in self.size.offsets() {
+            let x = i32::from(pos.x) + dx * i32::from(width);
+            let y = i32::from(pos.y) + dy;
+            if x < 0 || y < 0 {
+                continue;
+            }
+
+            let point = grid::Point::new(x as u16, y as u16);
+            let columns = (0..width).map(|column| grid::Point::new(point.x + column, point.y));
+            if columns.clone().any(|column| segment.contains(column)) {
+                continue;
+            }
+            for column in columns {
+                segment.add(if column == point {
+                    grid::Cell::grapheme(column, &glyph)
+                } else {
+                    grid::Cell::continuation(column)
+                });
+            }
+        }
+    }
+}
+
+impl Default for Brush {
     fn default() -> Self {
-        Tool::Plot
+        Self { char: '#', size: Default::default() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Shape {
+    Line(grid::Point, grid::Point),
+    Rectangle(grid::Point, grid::Point),
+    Arrow(grid::Point, grid::Point),
+    TextBox(grid::Point, grid::Point, String),
+    Ellipse(grid::Point, grid::Point),
+}
+
+impl Shape {
+    pub fn rasterize(&self, tracer: &grid::Tracer) -> grid::Segment {
+        match self {
+            Shape::Line(from, to) => tracer.trace(*from, *to),
+            Shape::Ellipse(from, to) => tracer.ellipse(*from, *to),
+            Shape::Arrow(from, to) => {
+                let dx = i32::from(to.x) - i32::from(from.x);
+                let dy = i32::from(to.y) - i32::from(from.y);
+                let head = match (dx, dy) {
+                    (0, 0) => return grid::Segment::new(),
+                    (dx, dy) if dx.abs() >= dy.abs() && dx > 0 => '>',
+                    (dx, dy) if dx.abs() >= dy.abs() => '<',
+                    (_, dy) if dy > 0 => 'v',
+                    _ => '^',
+                };
+
+                // The head takes the place of the line's last cell
+                let mut segment = grid::Segment::new();
+                for cell in tracer.trace(*from, *to).cells().iter().filter(|cell| cell.pos() != *to)
+                {
+                    segment.add(cell.clone());
+                }
+                segment.add(grid::Cell::new(*to, head));
+                segment
+            }
+            Shape::Rectangle(from, to) => {
+                let (start, end) = corners(*from, *to);
+                let mut segment = grid::Segment::new();
+                if end.x > start.x {
+                    segment += tracer.trace(start, grid::Point::new(end.x - 1, start.y));
+                    segment +=
+                        tracer.trace(grid::Point::new(start.x, end.y), (end.x - 1, end.y).into());
+                }
+                segment += tracer.trace(start, grid::Point::new(start.x, end.y));
+                segment += tracer.trace(grid::Point::new(end.x, start.y), end);
+                segment
+            }
+            Shape::TextBox(from, to, text) => {
+                let (start, end) = corners(*from, *to);
+                let mut segment = Shape::Rectangle(start, end).rasterize(tracer);
+
+                if end.x > start.x + 1 && end.y > start.y + 1 {
+                    segment += grid::Segment::from_str_wrapped(
+                        grid::Point::new(start.x + 1, start.y + 1),
+                        grid::Point::new(end.x - 1, end.y - 1),
+                        text,
+                        Default::default(),
+                    );
+                }
+                segment
+            }
+        }
+    }
+
+    /// Points that can be dragged to reshape this shape; the two ends of a line, or the four
+    /// corners of a box (or of the box bounding an ellipse).
+    pub fn handles(&self) -> Vec<grid::Point> {
+        match self {
+            Shape::Line(from, to) | Shape::Arrow(from, to) => vec![*from, *to],
+            Shape::Rectangle(from, to) | Shape::TextBox(from, to, _) | Shape::Ellipse(from, to) => {
+                vec![*from, grid::Point::new(to.x, from.y), *to, grid::Point::new(from.x, to.y)]
+            }
+        }
+    }
+
+    pub fn move_handle(&mut self, handle: usize, pos: grid::Point) {
+        match self {
+            Shape::Line(from, to) | Shape::Arrow(from, to) => match handle {
+                0 => *from = pos,
+                _ => *to = pos,
+            },
+            Shape::Rectangle(from, to) | Shape::TextBox(from, to, _) | Shape::Ellipse(from, to) => {
+                match handle {
+                    0 => *from = pos,
+                    1 => {
+                        to.x = pos.x;
+                        from.y = pos.y;
+                    }
+                    2 => *to = pos,
+                    _ => {
+                        from.x = pos.x;
+                        to.y = pos.y;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn translate(&mut self, dx: i32, dy: i32) {
+        let shift = |point: &mut grid::Point| {
+            point.x = (i32::from(point.x) + dx).max(0) as u16;
+            point.y = (i32::from(point.y) + dy).max(0) as u16;
+        };
+        match self {
+            Shape::Line(from, to)
+            | Shape::Arrow(from, to)
+            | Shape::Rectangle(from, to)
+            | Shape::TextBox(from, to, _)
+            | Shape::Ellipse(from, to) => {
+                shift(from);
+                shift(to);
+            }
+        }
     }
 }
 
-#[derive(Debug, Default)]
+fn corners(a: grid::Point, b: grid::Point) -> (grid::Point, grid::Point) {
+    (grid::Point::new(a.x.min(b.x), a.y.min(b.y)), grid::Point::new(a.x.max(b.x), a.y.max(b.y)))
+}
+
+#[derive(Debug, Clone)]
+enum Element {
+    Stroke(grid::Segment),
+    Shape(Shape, terminal::Format),
+    Pixels(Vec<grid::Segment>),
+}
+
+impl Element {
+    fn rasterize(&self, tracer: &grid::Tracer) -> grid::Segment {
+        match self {
+            Element::Stroke(segment) => segment.clone(),
+            Element::Shape(shape, format) => {
+                let mut segment = shape.rasterize(tracer);
+                segment.set_format(*format);
+                segment
+            }
+            Element::Pixels(segments) => segments.iter().sum(),
+        }
+    }
+
+    /// Like `rasterize`, but keeps cells that have their own format apart.
+    fn segments(&self, tracer: &grid::Tracer) -> Vec<grid::Segment> {
+        match self {
+            Element::Pixels(segments) => segments.clone(),
+            _ => vec![self.rasterize(tracer)],
+        }
+    }
+}
+
+/// A shape or stroke copied out of a canvas.
+#[derive(Debug, Clone)]
+pub struct Clip(Element);
+
+/// A shape or stroke picked with the edit tool; either by one of a shape's handles, or by its
+/// body, in which case dragging moves the whole element relative to `anchor`.
+#[derive(Debug, Copy, Clone)]
+struct Selection {
+    layer: usize,
+    element: usize,
+    handle: Option<usize>,
+    anchor: grid::Point,
+}
+
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    pub locked: bool,
+    design: Vec<Element>,
+}
+
+impl Layer {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), visible: true, locked: false, design: Vec::new() }
+    }
+}
+
+#[derive(Debug)]
 pub struct Canvas {
     pub cursor: grid::Point,
-    design: Vec<grid::Segment>,
+    pub tracer: grid::Tracer,
+    layers: Vec<Layer>,
+    active: usize,
+    selection: Option<Selection>,
+    dirty: bool,
+    /// The page, if any, along with the position of its top left cell.
+    page: Option<(grid::Point, Page)>,
+    /// How far the canvas is scrolled right and down.
+    scroll: grid::Point,
 }
 
 impl Canvas {
-    pub fn new() -> Self {
-        Self { design: Vec::new(), cursor: Default::default() }
+    pub fn new(tracer: grid::Tracer) -> Self {
+        Self {
+            cursor: Default::default(),
+            tracer,
+            layers: vec![Layer::new("Base")],
+            active: 0,
+            selection: None,
+            dirty: false,
+            page: None,
+            scroll: grid::Point::new(0, 0),
+        }
     }
 
+    /// Adds `segment` to the active layer, unless it's locked.
     pub fn add(&mut self, segment: grid::Segment) {
-        self.design.push(segment)
+        self.push(Element::Stroke(segment));
+    }
+
+    /// Adds `shape` to the active layer, unless it's locked. Unlike segments, shapes can be
+    /// reshaped and deleted later on with the edit tool.
+    pub fn add_shape(&mut self, shape: Shape, format: terminal::Format) {
+        self.push(Element::Shape(shape, format));
+    }
+
+    /// Adds half block cells painted in pixel mode to the active layer, unless it's locked.
+    pub fn add_pixels(&mut self, segments: Vec<grid::Segment>) {
+        self.push(Element::Pixels(segments));
+    }
+
+    fn push(&mut self, element: Element) {
+        let layer = &mut self.layers[self.active];
+        if !layer.locked {
+            layer.design.push(element);
+            self.dirty = true;
+        }
     }
 
     pub fn undo(&mut self) -> Option<grid::Segment> {
-        self.design.pop()
+        let layer = &mut self.layers[self.active];
+        if layer.locked {
+            return None;
+        }
+
+        let element = layer.design.pop()?;
+        self.selection = None;
+        self.dirty = true;
+        Some(element.rasterize(&self.tracer))
     }
 
     pub fn clear(&mut self) {
-        self.design.iter_mut().for_each(|segment| segment.clear());
+        self.layers.iter_mut().filter(|layer| !layer.locked).for_each(|layer| layer.design.clear());
+        self.selection = None;
+        self.dirty = true;
+    }
+
+    /// Selects the topmost shape or stroke of the active layer at `pos`, returning whether one
+    /// was found.
+    pub fn select_at(&mut self, pos: grid::Point) -> bool {
+        let layer = &self.layers[self.active];
+        self.selection = None;
+        if layer.locked || !layer.visible {
+            return false;
+        }
+
+        for (index, element) in layer.design.iter().enumerate().rev() {
+            let handle = match element {
+                Element::Shape(shape, _) => {
+                    shape.handles().iter().position(|handle| *handle == pos)
+                }
+                Element::Stroke(_) => None,
+                Element::Pixels(_) => continue,
+            };
+            if handle.is_some() || element.rasterize(&self.tracer).contains(pos) {
+                self.selection =
+                    Some(Selection { layer: self.active, element: index, handle, anchor: pos });
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Drags the selected handle, or the whole selected shape, to `pos`. Returns the shape as it
+    /// was rendered before the move, so that it can be erased from the screen.
+    pub fn drag_selection(&mut self, pos: grid::Point) -> Option<grid::Segment> {
+        let selection = self.selection.as_mut()?;
+        let layer = &self.layers[selection.layer];
+        if layer.locked || !layer.visible {
+            return None;
+        }
+        let element = &mut self.layers[selection.layer].design[selection.element];
+        let previous = element.rasterize(&self.tracer);
+
+        let (dx, dy) = (
+            i32::from(pos.x) - i32::from(selection.anchor.x),
+            i32::from(pos.y) - i32::from(selection.anchor.y),
+        );
+        match (element, selection.handle) {
+            (Element::Shape(shape, _), Some(handle)) => shape.move_handle(handle, pos),
+            (Element::Shape(shape, _), None) => shape.translate(dx, dy),
+            (Element::Stroke(segment), _) => segment.translate(dx, dy),
+            (Element::Pixels(_), _) => {}
+        }
+
+        selection.anchor = pos;
+        self.dirty = true;
+        Some(previous)
+    }
+
+    /// A copy of the selected element, to paste into this or another canvas.
+    pub fn copy_selection(&self) -> Option<Clip> {
+        let selection = self.selection?;
+        Some(Clip(self.layers[selection.layer].design[selection.element].clone()))
+    }
+
+    /// Adds a copied element to the active layer, unless it's locked, and selects it.
+    pub fn paste(&mut self, clip: &Clip) {
+        let layer = &self.layers[self.active];
+        if layer.locked {
+            return;
+        }
+        self.push(clip.0.clone());
+        let element = self.layers[self.active].design.len() - 1;
+        let anchor = self.cursor;
+        self.selection = Some(Selection { layer: self.active, element, handle: None, anchor });
+    }
+
+    /// Removes the selected shape, leaving the rest of the layer's history intact.
+    pub fn delete_selection(&mut self) -> Option<grid::Segment> {
+        let selection = self.selection.take()?;
+        let layer = &self.layers[selection.layer];
+        if layer.locked || !layer.visible {
+            return None;
+        }
+        let element = self.layers[selection.layer].design.remove(selection.element);
+        self.dirty = true;
+        Some(element.rasterize(&self.tracer))
+    }
+
+    pub fn deselect(&mut self) {
+        self.selection = None;
+    }
+
+    pub fn selection_handles(&self) -> Vec<grid::Point> {
+        match self.selection {
+            Some(selection) => match &self.layers[selection.layer].design[selection.element] {
+                Element::Shape(shape, _) => shape.handles(),
+                Element::Stroke(_) | Element::Pixels(_) => Vec::new(),
+            },
+            None => Vec::new(),
+        }
+    }
+
+    /// Columns and rows that the shapes on visible layers line up on: the edges and centres of
+    /// their boundaries.
+    pub fn alignment(&self) -> (Vec<u16>, Vec<u16>) {
+        let (mut columns, mut rows) = (Vec::new(), Vec::new());
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            for element in &layer.design {
+                if let Element::Shape(shape, _) = element {
+                    if let Some((start, end)) = shape.rasterize(&self.tracer).boundaries() {
+                        columns.extend([start.x, (start.x + end.x) / 2, end.x]);
+                        rows.extend([start.y, (start.y + end.y) / 2, end.y]);
+                    }
+                }
+            }
+        }
+        columns.sort_unstable();
+        columns.dedup();
+        rows.sort_unstable();
+        rows.dedup();
+        (columns, rows)
+    }
+
+    /// The cells of the selected element, as they're drawn.
+    pub fn selected_segment(&self) -> Option<grid::Segment> {
+        let selection = self.selection?;
+        Some(self.layers[selection.layer].design[selection.element].rasterize(&self.tracer))
+    }
+
+    /// Width and height of the selected element.
+    pub fn selection_size(&self) -> Option<(u16, u16)> {
+        let selection = self.selection?;
+        let element = &self.layers[selection.layer].design[selection.element];
+        let (start, end) = element.rasterize(&self.tracer).boundaries()?;
+        Some((end.x - start.x + 1, end.y - start.y + 1))
+    }
+
+    /// Limits the sketch to `page`, with its top left cell at `origin`, or lifts the limit.
+    pub fn set_page(&mut self, origin: grid::Point, page: Option<Page>) {
+        self.page = page.map(|page| (origin, page));
+    }
+
+    pub fn page(&self) -> Option<Page> {
+        self.page.map(|(_, page)| page)
+    }
+
+    /// The top left and bottom right cells of the page.
+    pub fn page_bounds(&self) -> Option<(grid::Point, grid::Point)> {
+        self.page.map(|(origin, page)| (origin, page.end(origin)))
+    }
+
+    /// Whether `pos` is on the page, which it always is without one.
+    pub fn on_page(&self, pos: grid::Point) -> bool {
+        self.page_bounds().is_none_or(|(start, end)| {
+            (start.x..=end.x).contains(&pos.x) && (start.y..=end.y).contains(&pos.y)
+        })
+    }
+
+    /// The part of the canvas shown in `area` of the screen.
+    pub fn viewport(&self, area: (grid::Point, grid::Point)) -> Viewport {
+        Viewport { area, offset: self.scroll }
+    }
+
+    pub fn scroll_to(&mut self, offset: grid::Point) {
+        self.scroll = offset;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn record(&self) -> Record {
+        Record { layers: self.layers.clone(), active: self.active }
+    }
+
+    /// Replaces the layers with those from `record`, as unsaved changes.
+    pub fn restore(&mut self, record: Record) {
+        self.layers = record.layers;
+        self.active = record.active;
+        self.selection = None;
+        self.dirty = true;
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.dirty = false;
+    }
+
+    pub fn add_layer(&mut self, name: &str) {
+        self.selection = None;
+        self.layers.push(Layer::new(name));
+        self.active = self.layers.len() - 1;
+    }
+
+    pub fn next_layer(&mut self) {
+        self.selection = None;
+        self.active = (self.active + 1) % self.layers.len();
+    }
+
+    pub fn previous_layer(&mut self) {
+        self.selection = None;
+        self.active = (self.active + self.layers.len() - 1) % self.layers.len();
+    }
+
+    /// Swaps the active layer with the one above it, keeping it active.
+    pub fn raise_layer(&mut self) {
+        self.selection = None;
+        if self.active + 1 < self.layers.len() {
+            self.layers.swap(self.active, self.active + 1);
+            self.active += 1;
+        }
+    }
+
+    /// Swaps the active layer with the one below it, keeping it active.
+    pub fn lower_layer(&mut self) {
+        self.selection = None;
+        if self.active > 0 {
+            self.layers.swap(self.active, self.active - 1);
+            self.active -= 1;
+        }
+    }
+
+    pub fn active_layer(&self) -> &Layer {
+        &self.layers[self.active]
+    }
+
+    pub fn active_layer_mut(&mut self) -> &mut Layer {
+        &mut self.layers[self.active]
+    }
+
+    pub fn layer_position(&self) -> (usize, usize) {
+        (self.active + 1, self.layers.len())
+    }
+
+    pub fn format_at(&self, pos: grid::Point) -> Option<terminal::Format> {
+        self.snapshot(true)
+            .into_iter()
+            .find(|segment| segment.contains(pos))
+            .map(|segment| segment.format())
+    }
+
+    /// Composes the layers bottom to top into one segment per format. Blank cells only erase
+    /// what's beneath them in their own layer, so layers can't erase each other, and Braille
+    /// patterns or half blocks in the same layer are merged dot by dot, or pixel by pixel. A wide
+    /// character is dropped as a whole once anything covers either of its columns.
+    ///
+    /// With a page, anything off it is clipped, and blank cells are added at its corners so
+    /// that exports come out exactly its size.
+    pub fn snapshot(&self, visible_only: bool) -> Vec<grid::Segment> {
+        let mut cells: HashMap<grid::Point, (grid::Cell, terminal::Format)> = HashMap::new();
+        for layer in self.layers.iter().filter(|layer| layer.visible || !visible_only) {
+            let mut layer_cells: HashMap<grid::Point, (grid::Cell, terminal::Format)> =
+                HashMap::new();
+            for segment in layer.design.iter().flat_map(|element| element.segments(&self.tracer)) {
+                for cell in segment.cells() {
+                    if let Some(partner) =
+                        layer_cells.get(&cell.pos()).and_then(|(below, _)| below.partner())
+                    {
+                        layer_cells.remove(&partner);
+                    }
+
+                    if cell.is_blank() {
+                        layer_cells.remove(&cell.pos());
+                        continue;
+                    }
+
+                    let merged = match (layer_cells.get(&cell.pos()), cell.char()) {
+                        (Some((below, below_format)), Some(above)) => below.char().map(|below| {
+                            let (content, format) = grid::Pixels::merge(
+                                (below, *below_format),
+                                (above, segment.format()),
+                            );
+                            (
+                                grid::Cell::new(cell.pos(), grid::Braille::merge(below, content)),
+                                format,
+                            )
+                        }),
+                        _ => None,
+                    };
+                    layer_cells.insert(
+                        cell.pos(),
+                        merged.unwrap_or_else(|| (cell.clone(), segment.format())),
+                    );
+                }
+            }
+
+            // Wide characters below that are half covered by this layer can't be drawn
+            let orphans: Vec<grid::Point> = layer_cells
+                .keys()
+                .filter_map(|pos| cells.get(pos).and_then(|(below, _)| below.partner()))
+                .collect();
+            for pos in orphans {
+                cells.remove(&pos);
+            }
+            cells.extend(layer_cells);
+        }
+
+        if let Some((start, end)) = self.page_bounds() {
+            cells.retain(|pos, (cell, _)| {
+                self.on_page(*pos) && cell.partner().is_none_or(|partner| self.on_page(partner))
+            });
+            // Without a height, only the width is padded out, on the top row
+            let rows = match self.page() {
+                Some(Page { height: Some(_), .. }) => Some((start.y, end.y)),
+                _ => cells.keys().map(|pos| pos.y).min().map(|top| (top, top)),
+            };
+            if let Some((top, bottom)) = rows {
+                for pos in [grid::Point::new(start.x, top), grid::Point::new(end.x, bottom)] {
+                    let blank = (grid::Cell::new(pos, ' '), terminal::Format::default());
+                    cells.entry(pos).or_insert(blank);
+                }
+            }
+        }
+
+        let mut segments: Vec<grid::Segment> = Vec::new();
+        for (cell, format) in cells.into_values() {
+            match segments.iter_mut().find(|segment| segment.format() == format) {
+                Some(segment) => segment.add(cell),
+                None => {
+                    let mut segment = grid::Segment::new();
+                    segment.set_format(format);
+                    segment.add(cell);
+                    segments.push(segment);
+                }
+            }
+        }
+        segments
     }
+}
+
+/// The layers of a canvas and everything on them, without the page or the undo history, as
+/// kept in the recovery file. Shapes stay shapes, so they can still be edited once restored.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "RawRecord", into = "RawRecord")]
+pub struct Record {
+    layers: Vec<Layer>,
+    active: usize,
+}
 
-    pub fn snapshot(&self) -> Vec<grid::Segment> {
-        self.design.clone()
+impl Record {
+    pub fn is_empty(&self) -> bool {
+        self.layers.iter().all(|layer| layer.design.is_empty())
     }
 }
 
-impl fmt::Display for Canvas {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.design.iter().try_for_each(|segment| write!(f, "{}", segment))
+#[derive(Deserialize, Serialize)]
+struct RawRecord {
+    active: usize,
+    layers: Vec<RawLayer>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct RawLayer {
+    name: String,
+    visible: bool,
+    locked: bool,
+    #[serde(default)]
+    elements: Vec<RawElement>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Kind {
+    Stroke,
+    Pixels,
+    Line,
+    Rectangle,
+    Arrow,
+    Text,
+    Ellipse,
+}
+
+#[derive(Deserialize, Serialize)]
+struct RawElement {
+    kind: Kind,
+    /// The two corners of a shape.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    points: Vec<[u16; 2]>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    text: String,
+    /// The cells of a stroke or of each colour of pixels, or just the format of a shape.
+    segments: Vec<RawSegment>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct RawSegment {
+    fg: config::Color,
+    bg: config::Color,
+    attributes: u8,
+    /// Each cell as `x,y,content`, where the content of a wide character's second column is
+    /// empty.
+    #[serde(default)]
+    cells: Vec<String>,
+}
+
+impl From<Record> for RawRecord {
+    fn from(record: Record) -> Self {
+        let raw_segment = |segment: &grid::Segment| {
+            let format = segment.format();
+            RawSegment {
+                fg: config::Color(format.fg_color),
+                bg: config::Color(format.bg_color),
+                attributes: format.attributes.into(),
+                cells: segment
+                    .cells()
+                    .iter()
+                    .map(|cell| format!("{},{},{}", cell.pos().x, cell.pos().y, cell.content()))
+                    .collect(),
+            }
+        };
+        let raw_element = |element: &Element| match element {
+            Element::Stroke(segment) => RawElement {
+                kind: Kind::Stroke,
+                points: Vec::new(),
+                text: String::new(),
+                segments: vec![raw_segment(segment)],
+            },
+            Element::Pixels(segments) => RawElement {
+                kind: Kind::Pixels,
+                points: Vec::new(),
+                text: String::new(),
+                segments: segments.iter().map(raw_segment).collect(),
+            },
+            Element::Shape(shape, format) => {
+                let (kind, from, to, text) = match shape {
+                    Shape::Line(from, to) => (Kind::Line, from, to, ""),
+                    Shape::Rectangle(from, to) => (Kind::Rectangle, from, to, ""),
+                    Shape::Arrow(from, to) => (Kind::Arrow, from, to, ""),
+                    Shape::TextBox(from, to, text) => (Kind::Text, from, to, text.as_str()),
+                    Shape::Ellipse(from, to) => (Kind::Ellipse, from, to, ""),
+                };
+                let mut segment = grid::Segment::new();
+                segment.set_format(*format);
+                RawElement {
+                    kind,
+                    points: vec![[from.x, from.y], [to.x, to.y]],
+                    text: text.to_string(),
+                    segments: vec![raw_segment(&segment)],
+                }
+            }
+        };
+
+        RawRecord {
+            active: record.active,
+            layers: record
+                .layers
+                .iter()
+                .map(|layer| RawLayer {
+                    name: layer.name.clone(),
+                    visible: layer.visible,
+                    locked: layer.locked,
+                    elements: layer.design.iter().map(raw_element).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<RawRecord> for Record {
+    type Error = crate::Error;
+
+    fn try_from(raw: RawRecord) -> crate::Result<Self> {
+        let segment = |raw: RawSegment| -> crate::Result<grid::Segment> {
+            let mut segment = grid::Segment::new();
+            segment.set_format(
+                terminal::Format::new(raw.bg.0, raw.fg.0).with_attributes(raw.attributes.into()),
+            );
+            for cell in raw.cells {
+                let mut parts = cell.splitn(3, ',');
+                let (x, y, content) = match (parts.next(), parts.next(), parts.next()) {
+                    (Some(x), Some(y), Some(content)) => (x.parse()?, y.parse()?, content),
+                    _ => return Err(format!("malformed cell `{}`", cell).into()),
+                };
+                let pos = grid::Point::new(x, y);
+                segment.add(match content {
+                    "" => grid::Cell::continuation(pos),
+                    content => grid::Cell::grapheme(pos, content),
+                });
+            }
+            Ok(segment)
+        };
+        let element = |raw: RawElement| -> crate::Result<Element> {
+            let mut segments =
+                raw.segments.into_iter().map(segment).collect::<crate::Result<Vec<_>>>()?;
+            let (from, to) = match raw.points.as_slice() {
+                [from, to] => (grid::Point::new(from[0], from[1]), grid::Point::new(to[0], to[1])),
+                _ => (grid::Point::default(), grid::Point::default()),
+            };
+            let shape = match raw.kind {
+                Kind::Pixels => return Ok(Element::Pixels(segments)),
+                Kind::Stroke if segments.len() == 1 => {
+                    return Ok(Element::Stroke(segments.remove(0)))
+                }
+                Kind::Stroke => return Err("a stroke needs exactly one segment".into()),
+                _ if raw.points.len() != 2 || segments.len() != 1 => {
+                    return Err("a shape needs two points and a format".into())
+                }
+                Kind::Line => Shape::Line(from, to),
+                Kind::Rectangle => Shape::Rectangle(from, to),
+                Kind::Arrow => Shape::Arrow(from, to),
+                Kind::Text => Shape::TextBox(from, to, raw.text),
+                Kind::Ellipse => Shape::Ellipse(from, to),
+            };
+            Ok(Element::Shape(shape, segments[0].format()))
+        };
+
+        let layers = raw
+            .layers
+            .into_iter()
+            .map(|raw| {
+                Ok(Layer {
+                    name: raw.name,
+                    visible: raw.visible,
+                    locked: raw.locked,
+                    design: raw.elements.into_iter().map(element).collect::<crate::Result<_>>()?,
+                })
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        if raw.active >= layers.len() {
+            return Err("no such active layer".into());
+        }
+        Ok(Record { layers, active: raw.active })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arrow_head_replaces_the_end_of_the_line() {
+        let arrow = Shape::Arrow((1, 1).into(), (5, 1).into());
+        let segment = arrow.rasterize(&grid::Tracer::default());
+        assert_eq!(segment.cells().len(), 4);
+        assert_eq!(String::from(segment), "___>\n");
+    }
+
+    #[test]
+    fn viewport_shows_what_is_scrolled_into_view() {
+        let mut canvas = Canvas::new(grid::Tracer::default());
+        let area = (grid::Point::new(1, 3), grid::Point::new(80, 23));
+        canvas.scroll_to(canvas.viewport(area).centred_on((100, 50).into()));
+        let viewport = canvas.viewport(area);
+        assert_eq!(viewport.bounds(), ((61, 40).into(), (140, 60).into()));
+        assert_eq!(viewport.to_canvas((40, 13).into()), (100, 50).into());
+
+        // Cut off at the left edge, along with the wide character across it
+        let segment = grid::Segment::from_str((58, 50).into(), "ab語x", Default::default());
+        let shown = viewport.show(&segment);
+        let cells: Vec<(grid::Point, &str)> =
+            shown.cells().iter().map(|cell| (cell.pos(), cell.content())).collect();
+        assert_eq!(cells, [(grid::Point::new(2, 13), "x")]);
+    }
+
+    fn sketch() -> Canvas {
+        let mut canvas = Canvas::new(grid::Tracer::default());
+        let format = terminal::Format::new(terminal::Color::Reset, terminal::Color::Red);
+        canvas.add(grid::Segment::from_str((1, 3).into(), "a語, b", format));
+        let mut pixels = grid::Pixels::new();
+        pixels.set((4, 8).into(), terminal::Color::Blue);
+        pixels.set((4, 9).into(), terminal::Color::Green);
+        pixels.set((6, 9).into(), terminal::Color::Blue);
+        canvas.add_pixels(pixels.to_segments());
+
+        canvas.add_layer("shapes");
+        for shape in [
+            Shape::Line((2, 5).into(), (8, 5).into()),
+            Shape::Rectangle((2, 6).into(), (9, 9).into()),
+            Shape::Arrow((3, 10).into(), (3, 14).into()),
+            Shape::TextBox((10, 4).into(), (20, 8).into(), "hi, 世界".to_string()),
+            Shape::Ellipse((12, 10).into(), (20, 16).into()),
+        ] {
+            canvas.add_shape(shape, format.with_attributes(terminal::Attribute::Bold.into()));
+        }
+        canvas.active_layer_mut().locked = true;
+        canvas.add_layer("hidden");
+        canvas.add(grid::Segment::from_str((30, 3).into(), "x", Default::default()));
+        canvas.active_layer_mut().visible = false;
+        canvas.previous_layer();
+        canvas
+    }
+
+    fn drawing(canvas: &Canvas) -> String {
+        String::from(canvas.snapshot(false).iter().sum::<grid::Segment>())
+    }
+
+    #[test]
+    fn record_round_trips_through_toml() {
+        let canvas = sketch();
+        let saved = toml::to_string(&canvas.record()).unwrap();
+        for kind in ["stroke", "pixels", "line", "rectangle", "arrow", "text", "ellipse"] {
+            assert!(saved.contains(&format!("kind = \"{}\"", kind)), "no {} in {}", kind, saved);
+        }
+        // The second column of 語 is a continuation cell
+        assert!(saved.contains("\"2,3,語\", \"3,3,\", \"4,3,,\""));
+
+        let record: Record = toml::from_str(&saved).unwrap();
+        assert_eq!(toml::to_string(&record).unwrap(), saved);
+        let mut restored = Canvas::new(grid::Tracer::default());
+        restored.restore(record);
+        assert_eq!(drawing(&restored), drawing(&canvas));
+        assert_eq!(restored.layer_position(), (2, 3));
+        assert!(restored.active_layer().locked);
+        assert_eq!(restored.snapshot(true).len(), canvas.snapshot(true).len());
+        assert!(restored.is_dirty());
+    }
+
+    #[test]
+    fn record_rejects_malformed_cells_and_layers() {
+        let saved = toml::to_string(&sketch().record()).unwrap();
+        let load = |saved: &str| toml::from_str::<Record>(saved).map(|_| ());
+        assert!(load(&saved).is_ok());
+        for (from, to) in [
+            ("\"1,3,a\"", "\"1,3\""),
+            ("\"1,3,a\"", "\"x,3,a\""),
+            ("\"1,3,a\"", "\"1,-3,a\""),
+            ("active = 1", "active = 3"),
+        ] {
+            assert!(saved.contains(from));
+            assert!(load(&saved.replacen(from, to, 1)).is_err(), "accepted {}", to);
+        }
+    }
+
+    #[test]
+    fn brush_stamps_wide_characters_across_two_columns() {
+        let brush = Brush { char: 'あ', size: BrushSize::Single };
+        let mut segment = grid::Segment::new();
+        for x in 1..=4 {
+            brush.stamp((x, 1).into(), &mut segment);
+        }
+        assert_eq!(segment.cells().len(), 4);
+        assert_eq!(String::from(segment), "ああ\n");
+
+        let brush = Brush { char: 'あ', size: BrushSize::Square };
+        let mut segment = grid::Segment::new();
+        brush.stamp((3, 2).into(), &mut segment);
+        assert_eq!(String::from(segment), "あああ\nあああ\nあああ\n");
+    }
+
+    #[test]
+    fn page_sizes_parse() {
+        assert_eq!("80x24".parse::<Page>().unwrap(), Page { width: 80, height: Some(24) });
+        assert_eq!("72".parse::<Page>().unwrap(), Page { width: 72, height: None });
+        for size in ["0x5", "5x0", "0", "x", "80x", "x24", "80x24x2", "-3", "wide"] {
+            let error = size.parse::<Page>().unwrap_err().to_string();
+            assert_eq!(
+                error,
+                format!("invalid page size `{}`, expected one like 80x24 or 72", size)
+            );
+        }
+        assert_eq!(Page { width: 80, height: Some(24) }.to_string(), "80x24");
+    }
+
+    #[test]
+    fn snapshot_comes_out_exactly_the_page_size() {
+        let mut canvas = Canvas::new(grid::Tracer::default());
+        let origin = grid::Point::new(1, 3);
+        canvas.add(grid::Segment::from_str((3, 4).into(), "hello, page", Default::default()));
+        // Half on the page, so dropped as a whole
+        canvas.add(grid::Segment::from_str((6, 3).into(), "語", Default::default()));
+
+        canvas.set_page(origin, Some(Page { width: 6, height: Some(3) }));
+        let text = String::from(canvas.snapshot(false).iter().sum::<grid::Segment>());
+        assert_eq!(text, "      \n  hell\n      \n");
+
+        // Without a height, only the width is fixed
+        canvas.set_page(origin, Some(Page { width: 16, height: None }));
+        let text = String::from(canvas.snapshot(false).iter().sum::<grid::Segment>());
+        assert_eq!(text, "     語         \n  hello, page   \n");
+    }
+
+    #[test]
+    fn snap_rounds_to_the_nearest_grid_line() {
+        let (origin, area) = (grid::Point::new(1, 3), ((1, 3).into(), (80, 23).into()));
+        let none = (Vec::new(), Vec::new());
+        assert_eq!(snap((2, 4).into(), origin, area, 4, &none), (1, 3).into());
+        assert_eq!(snap((3, 5).into(), origin, area, 4, &none), (5, 7).into());
+        assert_eq!(snap((7, 9).into(), origin, area, 4, &none), (9, 11).into());
+        // With no grid, only alignment moves anything
+        assert_eq!(snap((7, 9).into(), origin, area, 0, &none), (7, 9).into());
+    }
+
+    #[test]
+    fn snap_prefers_alignment_within_a_cell() {
+        let (origin, area) = (grid::Point::new(1, 3), ((1, 3).into(), (80, 23).into()));
+        let alignment = (vec![8, 20], vec![10]);
+        assert_eq!(snap((7, 11).into(), origin, area, 4, &alignment), (8, 10).into());
+        assert_eq!(snap((9, 12).into(), origin, area, 4, &alignment), (8, 11).into());
+        assert_eq!(snap((18, 8).into(), origin, area, 4, &alignment), (17, 7).into());
+        assert_eq!(snap((21, 9).into(), origin, area, 0, &alignment), (20, 10).into());
+    }
+
+    #[test]
+    fn snap_stays_on_the_canvas() {
+        let (origin, area) = (grid::Point::new(1, 3), ((1, 3).into(), (80, 23).into()));
+        let none = (Vec::new(), Vec::new());
+        // 81 and 24 would be nearer, but are off the edge
+        assert_eq!(snap((80, 23).into(), origin, area, 4, &none), (77, 23).into());
+        assert_eq!(snap((80, 22).into(), origin, area, 5, &none), (76, 23).into());
+        let alignment = (vec![81], vec![2]);
+        assert_eq!(snap((80, 3).into(), origin, area, 0, &alignment), (80, 3).into());
     }
 }
@@ -0,0 +1,235 @@
+use std::path;
+use std::str;
+
+use terminal::grid;
+
+use crate::canvas;
+use crate::export;
+use crate::menu;
+
+/// Something typed at the `:` command line. A trailing `!` forces commands that would
+/// otherwise refuse to lose changes or overwrite files.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Write {
+        path: Option<String>,
+        force: bool,
+    },
+    Edit {
+        path: String,
+        force: bool,
+    },
+    Export {
+        mode: export::Mode,
+        path: String,
+    },
+    Charset(grid::CharSet),
+    /// Opens another sketch, blank or from a file.
+    New(Option<String>),
+    NewLayer(String),
+    /// Changes the page size, or removes the page when `None`.
+    Resize(Option<canvas::Page>),
+    Guides(u16),
+    Quit {
+        force: bool,
+    },
+    WriteQuit,
+}
+
+/// Command names, in the order they're completed.
+pub static NAMES: [&str; 11] =
+    ["charset", "e", "edit", "export", "guides", "layer", "new", "q", "resize", "w", "wq"];
+
+#[rustfmt::skip]
+static CHARSETS: [(&str, grid::CharSet); 4] = [
+    ("ascii", grid::CharSet {
+        stationary: '.', up: '|', down: '|', left: '_', right: '_',
+        backward_diagonal: '\\', forward_diagonal: '/',
+    }),
+    ("unicode", grid::CharSet {
+        stationary: '·', up: '│', down: '│', left: '─', right: '─',
+        backward_diagonal: '╲', forward_diagonal: '╱',
+    }),
+    ("unicode-heavy", grid::CharSet {
+        stationary: '•', up: '┃', down: '┃', left: '━', right: '━',
+        backward_diagonal: '╲', forward_diagonal: '╱',
+    }),
+    ("unicode-double", grid::CharSet {
+        stationary: '·', up: '║', down: '║', left: '═', right: '═',
+        backward_diagonal: '╲', forward_diagonal: '╱',
+    }),
+];
+
+impl str::FromStr for Command {
+    type Err = crate::Error;
+
+    fn from_str(line: &str) -> crate::Result<Self> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("no command given")?;
+        let (name, force) = match name.strip_suffix('!') {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        let args: Vec<&str> = words.collect();
+
+        let command = match (name, args.as_slice()) {
+            ("w", []) => Command::Write { path: None, force },
+            ("w", [path]) => Command::Write { path: Some(path.to_string()), force },
+            ("e", [path]) | ("edit", [path]) => Command::Edit { path: path.to_string(), force },
+            ("export", [mode, path]) => {
+                Command::Export { mode: mode.parse()?, path: path.to_string() }
+            }
+            ("charset", [name]) => {
+                let (_, charset) = CHARSETS.iter().find(|(n, _)| n == name).ok_or_else(|| {
+                    let names: Vec<&str> = CHARSETS.iter().map(|(name, _)| *name).collect();
+                    format!("unknown charset `{}`, expected one of: {}", name, names.join(", "))
+                })?;
+                Command::Charset(charset.clone())
+            }
+            ("layer", ["new", names @ ..]) if !names.is_empty() => {
+                Command::NewLayer(names.join(" "))
+            }
+            ("new", []) => Command::New(None),
+            ("new", [path]) => Command::New(Some(path.to_string())),
+            ("resize", ["none"]) => Command::Resize(None),
+            ("resize", [size]) => Command::Resize(Some(size.parse()?)),
+            ("guides", [cells]) => Command::Guides(
+                cells.parse().map_err(|_| format!("invalid number of cells `{}`", cells))?,
+            ),
+            ("q", []) => Command::Quit { force },
+            ("wq", []) => Command::WriteQuit,
+            _ => return Err(usage(name).into()),
+        };
+        Ok(command)
+    }
+}
+
+fn usage(name: &str) -> String {
+    let usage = match name {
+        "w" => "w [path]",
+        "e" | "edit" => "e <path>",
+        "export" => "export <format> <path>",
+        "charset" => "charset <name>",
+        "layer" => "layer new <name>",
+        "new" => "new [path]",
+        "resize" => "resize <width>x<height>|<width>|none",
+        "guides" => "guides <cells>",
+        "q" => "q",
+        "wq" => "wq",
+        _ => {
+            return format!("unknown command `{}`, expected one of: {}", name, NAMES.join(", "));
+        }
+    };
+    format!("usage: :{}", usage)
+}
+
+/// Completes the last word of `line`: the command name, then its arguments, with paths taken
+/// relative to `base`.
+pub fn complete(line: &str, base: &path::Path) -> Option<String> {
+    let (head, word) = match line.rfind(' ') {
+        Some(index) => line.split_at(index + 1),
+        None => ("", line),
+    };
+    let args: Vec<&str> = head.split_whitespace().collect();
+
+    let candidates: Vec<&str> = match args.as_slice() {
+        [] => NAMES.to_vec(),
+        ["charset"] => CHARSETS.iter().map(|(name, _)| *name).collect(),
+        ["export"] => export::MODE_NAMES.to_vec(),
+        ["layer"] => vec!["new"],
+        [name, ..] if ["w", "w!", "e", "e!", "edit", "edit!", "new", "export"].contains(name) => {
+            return menu::complete_path(base, word).map(|path| format!("{}{}", head, path));
+        }
+        _ => return None,
+    };
+
+    let matching: Vec<&str> =
+        candidates.into_iter().filter(|candidate| candidate.starts_with(word)).collect();
+    let completion = match matching.as_slice() {
+        [] => return None,
+        // Only one choice, so move on to the next word
+        [only] => format!("{} ", only),
+        [first, ..] => {
+            let shared = matching.iter().fold(first.len(), |len, candidate| {
+                first.bytes().zip(candidate.bytes()).take(len).take_while(|(a, b)| a == b).count()
+            });
+            first[..shared].to_string()
+        }
+    };
+    Some(format!("{}{}", head, completion))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Command {
+        line.parse().unwrap()
+    }
+
+    fn error(line: &str) -> String {
+        line.parse::<Command>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_writes_and_edits() {
+        assert!(matches!(parse("w"), Command::Write { path: None, force: false }));
+        assert!(matches!(
+            parse("w! out.txt"),
+            Command::Write { path: Some(path), force: true } if path == "out.txt"
+        ));
+        assert!(
+            matches!(parse("edit in.txt"), Command::Edit { path, force: false } if path == "in.txt")
+        );
+        assert!(matches!(parse("q!"), Command::Quit { force: true }));
+    }
+
+    #[test]
+    fn parses_arguments() {
+        assert!(matches!(
+            parse("export svg out.svg"),
+            Command::Export { mode: export::Mode::Svg, path } if path == "out.svg"
+        ));
+        assert!(matches!(parse("charset unicode-heavy"), Command::Charset(set) if set.up == '┃'));
+        assert!(matches!(
+            parse("resize 120x40"),
+            Command::Resize(Some(canvas::Page { width: 120, height: Some(40) }))
+        ));
+        assert!(matches!(parse("resize none"), Command::Resize(None)));
+        assert!(
+            matches!(parse("layer new  some notes"), Command::NewLayer(name) if name == "some notes")
+        );
+        assert!(matches!(parse("guides 8"), Command::Guides(8)));
+    }
+
+    #[test]
+    fn rejects_unknown_commands_and_arguments() {
+        assert!(error("frobnicate").starts_with("unknown command `frobnicate`, expected one of: "));
+        assert_eq!(error("layer new"), "usage: :layer new <name>");
+        assert_eq!(error("e"), "usage: :e <path>");
+        assert_eq!(
+            error("charset fancy"),
+            "unknown charset `fancy`, expected one of: ascii, unicode, unicode-heavy, unicode-double"
+        );
+        assert_eq!(error("guides many"), "invalid number of cells `many`");
+    }
+
+    #[test]
+    fn completes_names_up_to_the_shared_prefix() {
+        let base = path::Path::new(".");
+        assert_eq!(complete("ch", base).as_deref(), Some("charset "));
+        assert_eq!(complete("e", base).as_deref(), Some("e"));
+        assert_eq!(complete("ex", base).as_deref(), Some("export "));
+        assert_eq!(complete("x", base), None);
+    }
+
+    #[test]
+    fn completes_arguments() {
+        let base = path::Path::new(".");
+        assert_eq!(complete("charset unicode-h", base).as_deref(), Some("charset unicode-heavy "));
+        assert_eq!(complete("charset u", base).as_deref(), Some("charset unicode"));
+        assert_eq!(complete("export s", base).as_deref(), Some("export svg "));
+        assert_eq!(complete("layer ", base).as_deref(), Some("layer new "));
+        assert_eq!(complete("q ", base), None);
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::io;
+use std::path;
+use std::time;
+
+use serde::{Deserialize, Serialize};
+use terminal::grid;
+
+use crate::canvas;
+use crate::theme;
+
+/// Settings that can be given in the config file, overridden by `SHKETCH_*` environment
+/// variables, which are in turn overridden by command line flags.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Tool selected at startup.
+    pub tool: canvas::Tool,
+    /// Directory new sketches are saved in.
+    pub save_dir: path::PathBuf,
+    /// Name of new sketches, where `{timestamp}` is replaced by the milliseconds since the
+    /// epoch and `{ext}` by the extension of the export format.
+    pub file_name: String,
+    /// Seconds between saves to the recovery file, or 0 to turn them off.
+    pub autosave_interval: u64,
+    /// Name of the theme to start with, either built in or defined under `themes`.
+    pub theme: String,
+    /// Size of the page to sketch in, if sketches should have a fixed size.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<canvas::Page>,
+    /// Cells between the dotted guide lines, or 0 to hide them.
+    pub guides: u16,
+    /// Cells between the grid lines that shapes snap to in snap mode, or 0 to only snap to
+    /// other shapes.
+    pub snap_spacing: u16,
+    pub charset: CharSet,
+    pub toolbar: ToolBar,
+    /// Themes defined in addition to the built-in ones, or replacing them.
+    pub themes: BTreeMap<String, theme::Theme>,
+    /// Key names mapped to action names, on top of the default bindings.
+    pub keys: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CharSet {
+    pub stationary: char,
+    pub up: char,
+    pub down: char,
+    pub left: char,
+    pub right: char,
+    pub backward_diagonal: char,
+    pub forward_diagonal: char,
+}
+
+/// Overrides for the highlight colours of whichever theme is in use.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolBar {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_fg: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_bg: Option<Color>,
+}
+
+/// A terminal colour, written as a name like `dark-red`, an ANSI value from 0 to 255, or
+/// `#rrggbb`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color(pub terminal::Color);
+
+/// Every setting that [`Config::set`] accepts, which can also be set from the environment as
+/// `SHKETCH_<NAME>`, with dots replaced by underscores.
+pub static KEYS: [&str; 17] = [
+    "tool",
+    "theme",
+    "page",
+    "guides",
+    "snap_spacing",
+    "save_dir",
+    "file_name",
+    "autosave_interval",
+    "charset.stationary",
+    "charset.up",
+    "charset.down",
+    "charset.left",
+    "charset.right",
+    "charset.backward_diagonal",
+    "charset.forward_diagonal",
+    "toolbar.highlight_fg",
+    "toolbar.highlight_bg",
+];
+
+#[rustfmt::skip]
+static COLOR_NAMES: [(&str, terminal::Color); 17] = [
+    ("reset", terminal::Color::Reset),
+    ("black", terminal::Color::Black),
+    ("dark-red", terminal::Color::DarkRed),
+    ("dark-green", terminal::Color::DarkGreen),
+    ("dark-yellow", terminal::Color::DarkYellow),
+    ("dark-blue", terminal::Color::DarkBlue),
+    ("dark-magenta", terminal::Color::DarkMagenta),
+    ("dark-cyan", terminal::Color::DarkCyan),
+    ("grey", terminal::Color::Grey),
+    ("dark-grey", terminal::Color::DarkGrey),
+    ("red", terminal::Color::Red),
+    ("green", terminal::Color::Green),
+    ("yellow", terminal::Color::Yellow),
+    ("blue", terminal::Color::Blue),
+    ("magenta", terminal::Color::Magenta),
+    ("cyan", terminal::Color::Cyan),
+    ("white", terminal::Color::White),
+];
+
+impl Config {
+    /// Reads the config file over the built-in defaults, then applies any environment
+    /// variables on top.
+    pub fn load() -> crate::Result<Self> {
+        let mut config = match file_path() {
+            Some(path) => match fs::read_to_string(&path) {
+                Ok(content) => toml::from_str(&content)
+                    .map_err(|error| format!("{}: {}", path.display(), error))?,
+                Err(ref error) if error.kind() == io::ErrorKind::NotFound => Self::default(),
+                Err(error) => return Err(format!("{}: {}", path.display(), error).into()),
+            },
+            None => Self::default(),
+        };
+
+        for key in KEYS.iter() {
+            let var = format!("SHKETCH_{}", key.replace('.', "_").to_uppercase());
+            if let Some(value) = env::var_os(&var) {
+                let value = value.to_string_lossy();
+                config.set(key, &value).map_err(|error| format!("{}: {}", var, error))?;
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Changes the setting named `key`, one of [`KEYS`], parsing `value` as that setting would
+    /// be written in the config file.
+    pub fn set(&mut self, key: &str, value: &str) -> crate::Result {
+        match key {
+            "tool" => self.tool = value.parse()?,
+            "theme" => self.theme = value.to_string(),
+            "page" if value == "none" => self.page = None,
+            "page" => self.page = Some(value.parse()?),
+            "guides" => {
+                self.guides =
+                    value.parse().map_err(|_| format!("invalid number of cells `{}`", value))?
+            }
+            "snap_spacing" => {
+                self.snap_spacing =
+                    value.parse().map_err(|_| format!("invalid number of cells `{}`", value))?
+            }
+            "save_dir" => self.save_dir = path::PathBuf::from(value),
+            "file_name" => self.file_name = value.to_string(),
+            "autosave_interval" => {
+                self.autosave_interval =
+                    value.parse().map_err(|_| format!("invalid number of seconds `{}`", value))?
+            }
+            "toolbar.highlight_fg" => {
+                self.toolbar.highlight_fg = Some(Color::try_from(value.to_string())?)
+            }
+            "toolbar.highlight_bg" => {
+                self.toolbar.highlight_bg = Some(Color::try_from(value.to_string())?)
+            }
+            _ if key.starts_with("charset.") => {
+                let mut chars = value.chars();
+                let char = match (chars.next(), chars.next()) {
+                    (Some(char), None) => char,
+                    _ => return Err(format!("expected a single character, got `{}`", value).into()),
+                };
+                *(match key {
+                    "charset.stationary" => &mut self.charset.stationary,
+                    "charset.up" => &mut self.charset.up,
+                    "charset.down" => &mut self.charset.down,
+                    "charset.left" => &mut self.charset.left,
+                    "charset.right" => &mut self.charset.right,
+                    "charset.backward_diagonal" => &mut self.charset.backward_diagonal,
+                    "charset.forward_diagonal" => &mut self.charset.forward_diagonal,
+                    _ => return Err(format!("unknown setting `{}`", key).into()),
+                }) = char;
+            }
+            _ => return Err(format!("unknown setting `{}`", key).into()),
+        }
+        Ok(())
+    }
+
+    /// The effective settings, in the config file's format.
+    pub fn to_toml(&self) -> crate::Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn autosave_interval(&self) -> Option<time::Duration> {
+        match self.autosave_interval {
+            0 => None,
+            seconds => Some(time::Duration::from_secs(seconds)),
+        }
+    }
+
+    /// Every theme to cycle through, along with the position of the one to start with.
+    pub fn themes(&self) -> crate::Result<(Vec<(String, theme::Theme)>, usize)> {
+        let mut themes: Vec<(String, theme::Theme)> =
+            theme::BUILT_IN.iter().map(|(name, theme)| (name.to_string(), **theme)).collect();
+        for (name, theme) in &self.themes {
+            match themes.iter_mut().find(|(n, _)| n == name) {
+                Some((_, built_in)) => *built_in = *theme,
+                None => themes.push((name.clone(), *theme)),
+            }
+        }
+        for (_, theme) in &mut themes {
+            theme.highlight.fg = self.toolbar.highlight_fg.unwrap_or(theme.highlight.fg);
+            theme.highlight.bg = self.toolbar.highlight_bg.unwrap_or(theme.highlight.bg);
+        }
+
+        match themes.iter().position(|(name, _)| *name == self.theme) {
+            Some(index) => Ok((themes, index)),
+            None => {
+                let names: Vec<&str> = themes.iter().map(|(name, _)| name.as_str()).collect();
+                let message = format!(
+                    "unknown theme `{}`, expected one of: {}",
+                    self.theme,
+                    names.join(", ")
+                );
+                Err(message.into())
+            }
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tool: canvas::Tool::default(),
+            save_dir: path::PathBuf::new(),
+            file_name: "shketch-{timestamp}.{ext}".to_string(),
+            autosave_interval: 30,
+            theme: "dark".to_string(),
+            page: None,
+            guides: 0,
+            snap_spacing: 4,
+            charset: CharSet::default(),
+            toolbar: ToolBar::default(),
+            themes: BTreeMap::new(),
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for CharSet {
+    fn default() -> Self {
+        grid::CharSet::default().into()
+    }
+}
+
+impl From<grid::CharSet> for CharSet {
+    fn from(set: grid::CharSet) -> Self {
+        Self {
+            stationary: set.stationary,
+            up: set.up,
+            down: set.down,
+            left: set.left,
+            right: set.right,
+            backward_diagonal: set.backward_diagonal,
+            forward_diagonal: set.forward_diagonal,
+        }
+    }
+}
+
+impl From<CharSet> for grid::CharSet {
+    fn from(set: CharSet) -> Self {
+        Self {
+            stationary: set.stationary,
+            up: set.up,
+            down: set.down,
+            left: set.left,
+            right: set.right,
+            backward_diagonal: set.backward_diagonal,
+            forward_diagonal: set.forward_diagonal,
+        }
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = crate::Error;
+
+    fn try_from(name: String) -> crate::Result<Self> {
+        let name = name.trim().to_lowercase();
+        if let Some((_, color)) = COLOR_NAMES.iter().find(|(n, _)| *n == name) {
+            return Ok(Color(*color));
+        }
+        if let Ok(value) = name.parse() {
+            return Ok(Color(terminal::Color::AnsiValue(value)));
+        }
+        // Checked for ASCII first, as the channels are sliced out by byte
+        if let Some(hex) = name.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii()) {
+            let channel = |i| u8::from_str_radix(&hex[i..i + 2], 16);
+            if let (Ok(r), Ok(g), Ok(b)) = (channel(0), channel(2), channel(4)) {
+                return Ok(Color(terminal::Color::Rgb { r, g, b }));
+            }
+        }
+
+        let names: Vec<&str> = COLOR_NAMES.iter().map(|(name, _)| *name).collect();
+        Err(format!(
+            "unknown colour `{}`, expected one of: {}, a number from 0 to 255 or #rrggbb",
+            name,
+            names.join(", ")
+        )
+        .into())
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        match color.0 {
+            terminal::Color::AnsiValue(value) => value.to_string(),
+            terminal::Color::Rgb { r, g, b } => format!("#{:02x}{:02x}{:02x}", r, g, b),
+            color => {
+                let (name, _) =
+                    COLOR_NAMES.iter().find(|(_, c)| *c == color).expect("unnamed colour");
+                name.to_string()
+            }
+        }
+    }
+}
+
+pub fn file_path() -> Option<path::PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => path::PathBuf::from(dir),
+        _ => path::PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("shketch").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(name: &str) -> crate::Result<terminal::Color> {
+        Color::try_from(name.to_string()).map(|color| color.0)
+    }
+
+    #[test]
+    fn parses_names_values_and_hex() {
+        assert_eq!(color("dark-red").unwrap(), terminal::Color::DarkRed);
+        assert_eq!(color("42").unwrap(), terminal::Color::AnsiValue(42));
+        assert_eq!(color("#FF8000").unwrap(), terminal::Color::Rgb { r: 255, g: 128, b: 0 });
+    }
+
+    #[test]
+    fn rejects_malformed_hex_without_panicking() {
+        for name in ["#aébcd", "#12345", "#1234567", "#gggggg"] {
+            let error = color(name).unwrap_err().to_string();
+            assert!(error.starts_with("unknown colour"), "{}", error);
+        }
+    }
+}
@@ -1,22 +1,540 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
 use std::path;
+use std::str;
 use std::time;
 
 use terminal::grid;
 
-pub fn to_file(blueprint: grid::Segment) -> crate::Result<String> {
-    let file_name = {
-        let time = time::SystemTime::now().duration_since(time::SystemTime::UNIX_EPOCH)?;
-        format!("shketch-{}.txt", time.as_millis())
+#[derive(Debug, Default, Clone)]
+pub enum Mode {
+    #[default]
+    Text,
+    Markdown(Markdown),
+    Ansi,
+    Png,
+    Svg,
+    Embed {
+        markdown: Markdown,
+        file_name: String,
+        marker: String,
+    },
+}
+
+impl Mode {
+    fn extension(&self) -> &str {
+        match self {
+            Mode::Text => "txt",
+            Mode::Ansi => "ans",
+            Mode::Png => "png",
+            Mode::Svg => "svg",
+            Mode::Markdown(_) | Mode::Embed { .. } => "md",
+        }
+    }
+}
+
+/// Names of the formats that can be exported to without further options.
+pub static MODE_NAMES: [&str; 5] = ["text", "markdown", "ansi", "png", "svg"];
+
+impl str::FromStr for Mode {
+    type Err = crate::Error;
+
+    fn from_str(name: &str) -> crate::Result<Self> {
+        match name {
+            "text" => Ok(Mode::Text),
+            "markdown" => Ok(Mode::Markdown(Markdown::new())),
+            "ansi" => Ok(Mode::Ansi),
+            "png" => Ok(Mode::Png),
+            "svg" => Ok(Mode::Svg),
+            _ => Err(format!(
+                "unknown format `{}`, expected one of: {}",
+                name,
+                MODE_NAMES.join(", ")
+            )
+            .into()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Markdown {
+    fence: Option<String>,
+}
+
+impl Markdown {
+    pub fn new() -> Self {
+        Self { fence: None }
+    }
+
+    pub fn fenced(mut self, lang: &str) -> Self {
+        self.fence = Some(lang.to_string());
+        self
+    }
+
+    pub fn render(&self, blueprint: grid::Segment) -> String {
+        let content: String = blueprint.into();
+        let rows: Vec<&str> = content.lines().map(str::trim_end).collect();
+
+        let first = rows.iter().position(|row| !row.is_empty()).unwrap_or(rows.len());
+        let last = rows.iter().rposition(|row| !row.is_empty()).map_or(first, |i| i + 1);
+        let rows = &rows[first..last];
+
+        let indent = rows
+            .iter()
+            .filter(|row| !row.is_empty())
+            .map(|row| row.chars().take_while(|c| *c == ' ').count())
+            .min()
+            .unwrap_or(0);
+
+        let mut output = String::new();
+        if let Some(ref lang) = self.fence {
+            output.push_str(&format!("```{}\n", lang));
+        }
+        for row in rows {
+            output.extend(row.chars().skip(indent));
+            output.push('\n');
+        }
+        if self.fence.is_some() {
+            output.push_str("```\n");
+        }
+
+        output
+    }
+}
+
+/// Saves to a new file in `dir`, named after `template`, where `{timestamp}` is replaced by
+/// the milliseconds since the epoch and `{ext}` by the extension for `mode`. A number is added
+/// to the name if there's already a file by that name, as when several sketches are saved in
+/// the same millisecond.
+pub fn to_file(
+    snapshot: Vec<grid::Segment>,
+    mode: &Mode,
+    dir: &path::Path,
+    template: &str,
+) -> crate::Result<String> {
+    let file_name = match mode {
+        Mode::Embed { file_name, .. } => file_name.clone(),
+        _ => {
+            let time = time::SystemTime::now().duration_since(time::SystemTime::UNIX_EPOCH)?;
+            let name = template
+                .replace("{timestamp}", &time.as_millis().to_string())
+                .replace("{ext}", mode.extension());
+            unused_path(&dir.join(name)).to_string_lossy().into_owned()
+        }
     };
-    to_file_as(blueprint, &file_name)?;
+    to_file_as(snapshot, &file_name, mode)?;
     Ok(file_name)
 }
 
-pub fn to_file_as(blueprint: grid::Segment, file_name: &str) -> crate::Result {
+fn unused_path(path: &path::Path) -> path::PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|ext| format!(".{}", ext.to_string_lossy()));
+    (1..)
+        .map(|count| match count {
+            1 => path.to_path_buf(),
+            _ => path.with_file_name(format!(
+                "{}-{}{}",
+                stem,
+                count,
+                extension.as_deref().unwrap_or_default()
+            )),
+        })
+        .find(|path| !path.exists())
+        .expect("endless candidates")
+}
+
+pub fn to_file_as(snapshot: Vec<grid::Segment>, file_name: &str, mode: &Mode) -> crate::Result {
+    let blueprint: grid::Segment = snapshot.iter().sum();
+    let content = match mode {
+        Mode::Text => String::from(blueprint).into_bytes(),
+        Mode::Markdown(markdown) => markdown.render(blueprint).into_bytes(),
+        Mode::Ansi => to_ansi(&snapshot).into_bytes(),
+        Mode::Png => to_png(&snapshot)?,
+        Mode::Svg => to_svg(&snapshot).into_bytes(),
+        Mode::Embed { markdown, marker, .. } => {
+            let document = fs::read_to_string(path::Path::new(&file_name))?;
+            splice(&document, marker, &markdown.render(blueprint))?.into_bytes()
+        }
+    };
+
     let mut file = fs::File::create(path::Path::new(&file_name))?;
-    let content: String = blueprint.into();
-    file.write_all(content.as_bytes())?;
+    file.write_all(&content)?;
     Ok(())
 }
+
+/// Reads a text export back, placing its top left corner at `origin`. Spaces are left out so
+/// that they don't cover anything drawn underneath.
+pub fn from_text(origin: grid::Point, content: &str) -> grid::Segment {
+    let mut segment = grid::Segment::new();
+    for (y, line) in (origin.y..).zip(content.lines()) {
+        let row = grid::Segment::from_str(grid::Point::new(origin.x, y), line, Default::default());
+        for cell in row.cells().iter().filter(|cell| !cell.is_blank()) {
+            segment.add(cell.clone());
+        }
+    }
+    segment
+}
+
+type Cells<'a> = HashMap<grid::Point, (&'a str, terminal::Format)>;
+
+/// Indexes the cells of a snapshot by position, along with the corners of their bounding box.
+fn index(snapshot: &[grid::Segment]) -> Option<(Cells<'_>, grid::Point, grid::Point)> {
+    let (start, end) = snapshot.iter().sum::<grid::Segment>().boundaries()?;
+    let cells = snapshot
+        .iter()
+        .flat_map(|segment| {
+            segment.cells().iter().map(move |cell| (cell.pos(), (cell.content(), segment.format())))
+        })
+        .collect();
+    Some((cells, start, end))
+}
+
+/// Renders the sketch as text with terminal escape codes for its colours and attributes, so
+/// that it can be printed with `cat`.
+pub fn to_ansi(snapshot: &[grid::Segment]) -> String {
+    let mut output = String::new();
+    let (cells, start, end) = match index(snapshot) {
+        Some(index) => index,
+        None => return output,
+    };
+
+    for y in start.y..=end.y {
+        let mut current = terminal::RESET_FORMAT;
+        for x in start.x..=end.x {
+            let (content, format) = cells
+                .get(&grid::Point::new(x, y))
+                .copied()
+                .unwrap_or((" ", terminal::RESET_FORMAT));
+            if format != current {
+                output.push_str(&format.to_string());
+                current = format;
+            }
+            output.push_str(content);
+        }
+        output.push_str(&format!("{}\n", terminal::RESET_FORMAT));
+    }
+
+    output
+}
+
+/// Renders the sketch as an RGBA image with two pixels per cell, stacked vertically, to match
+/// pixel mode. Cells that aren't half blocks fill both pixels with their foreground colour, and
+/// wide characters fill the pixels of both their columns.
+pub fn to_png(snapshot: &[grid::Segment]) -> crate::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    let (cells, start, end) = match index(snapshot) {
+        Some(index) => index,
+        None => return Ok(output),
+    };
+
+    let width = u32::from(end.x - start.x) + 1;
+    let height = (u32::from(end.y - start.y) + 1) * 2;
+    let mut data = vec![0; (width * height * 4) as usize];
+
+    for (pos, (content, format)) in &cells {
+        let half_block = content.parse().ok().and_then(|char| grid::Pixels::decode(char, *format));
+        let (top, bottom) = match half_block {
+            Some(pixels) => pixels,
+            None if *content == " " => continue,
+            None => match format.fg_color {
+                terminal::Color::Reset => {
+                    (Some(terminal::Color::Black), Some(terminal::Color::Black))
+                }
+                color => (Some(color), Some(color)),
+            },
+        };
+
+        let x = u32::from(pos.x - start.x);
+        let y = u32::from(pos.y - start.y) * 2;
+        for (row, color) in [(y, top), (y + 1, bottom)].iter() {
+            if let Some([r, g, b]) = color.and_then(rgb) {
+                let offset = ((row * width + x) * 4) as usize;
+                data[offset..offset + 4].copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+    }
+
+    let mut encoder = png::Encoder::new(&mut output, width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(output)
+}
+
+/// Renders the sketch as an SVG image, each cell 10 by 20 units with its content in a
+/// monospace font, over a rectangle of its background colour if it has one. Terminal default
+/// colours come out black on transparent.
+pub fn to_svg(snapshot: &[grid::Segment]) -> String {
+    const CELL: (u32, u32) = (10, 20);
+    let (cells, start, end) = match index(snapshot) {
+        Some(index) => index,
+        None => (HashMap::new(), grid::Point::new(1, 1), grid::Point::new(0, 0)),
+    };
+    let width = (u32::from(end.x) + 1).saturating_sub(u32::from(start.x)) * CELL.0;
+    let height = (u32::from(end.y) + 1).saturating_sub(u32::from(start.y)) * CELL.1;
+    let hex = |[r, g, b]: [u8; 3]| format!("#{:02x}{:02x}{:02x}", r, g, b);
+
+    let mut cells: Vec<_> = cells.into_iter().collect();
+    cells.sort_by_key(|(pos, _)| (pos.y, pos.x));
+    let (mut backgrounds, mut text) = (String::new(), String::new());
+    for (pos, (content, format)) in cells {
+        let x = u32::from(pos.x - start.x) * CELL.0;
+        let y = u32::from(pos.y - start.y) * CELL.1;
+        let (mut fg, mut bg) = (rgb(format.fg_color), rgb(format.bg_color));
+        if format.attributes.has(terminal::Attribute::Reverse) {
+            (fg, bg) = (bg.or(Some([255, 255, 255])), fg.or(Some([0, 0, 0])));
+        }
+        if let Some(bg) = bg {
+            let cell_width = CELL.0 * grid::text_width(content).max(1) as u32;
+            backgrounds.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                x,
+                y,
+                cell_width,
+                CELL.1,
+                hex(bg)
+            ));
+        }
+        if content.trim().is_empty() {
+            continue;
+        }
+
+        let mut style = format!(" fill=\"{}\"", hex(fg.unwrap_or([0, 0, 0])));
+        for (attribute, property) in [
+            (terminal::Attribute::Bold, " font-weight=\"bold\""),
+            (terminal::Attribute::Dim, " opacity=\"0.5\""),
+            (terminal::Attribute::Italic, " font-style=\"italic\""),
+        ] {
+            if format.attributes.has(attribute) {
+                style.push_str(property);
+            }
+        }
+        // Both go in the one attribute, which can't be repeated
+        let decorations: Vec<&str> = [
+            (terminal::Attribute::Underline, "underline"),
+            (terminal::Attribute::Strikethrough, "line-through"),
+        ]
+        .iter()
+        .filter(|(attribute, _)| format.attributes.has(*attribute))
+        .map(|(_, decoration)| *decoration)
+        .collect();
+        if !decorations.is_empty() {
+            style.push_str(&format!(" text-decoration=\"{}\"", decorations.join(" ")));
+        }
+        let escaped = content.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        text.push_str(&format!("<text x=\"{}\" y=\"{}\"{}>{}</text>\n", x, y + 15, style, escaped));
+    }
+
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+         viewBox=\"0 0 {0} {1}\" font-family=\"monospace\" font-size=\"16\" \
+         xml:space=\"preserve\">\n{2}{3}</svg>\n",
+        width, height, backgrounds, text
+    )
+}
+
+/// Approximates a terminal colour in RGB, using the xterm defaults for named colours.
+fn rgb(color: terminal::Color) -> Option<[u8; 3]> {
+    const NAMED: [terminal::Color; 16] = [
+        terminal::Color::Black,
+        terminal::Color::DarkRed,
+        terminal::Color::DarkGreen,
+        terminal::Color::DarkYellow,
+        terminal::Color::DarkBlue,
+        terminal::Color::DarkMagenta,
+        terminal::Color::DarkCyan,
+        terminal::Color::Grey,
+        terminal::Color::DarkGrey,
+        terminal::Color::Red,
+        terminal::Color::Green,
+        terminal::Color::Yellow,
+        terminal::Color::Blue,
+        terminal::Color::Magenta,
+        terminal::Color::Cyan,
+        terminal::Color::White,
+    ];
+    const NAMED_RGB: [[u8; 3]; 16] = [
+        [0, 0, 0],
+        [128, 0, 0],
+        [0, 128, 0],
+        [128, 128, 0],
+        [0, 0, 128],
+        [128, 0, 128],
+        [0, 128, 128],
+        [192, 192, 192],
+        [128, 128, 128],
+        [255, 0, 0],
+        [0, 255, 0],
+        [255, 255, 0],
+        [0, 0, 255],
+        [255, 0, 255],
+        [0, 255, 255],
+        [255, 255, 255],
+    ];
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    match color {
+        terminal::Color::Reset => None,
+        terminal::Color::Rgb { r, g, b } => Some([r, g, b]),
+        terminal::Color::AnsiValue(value @ 0..=15) => Some(NAMED_RGB[value as usize]),
+        terminal::Color::AnsiValue(value @ 16..=231) => {
+            let value = value - 16;
+            Some([
+                LEVELS[(value / 36) as usize],
+                LEVELS[(value / 6 % 6) as usize],
+                LEVELS[(value % 6) as usize],
+            ])
+        }
+        terminal::Color::AnsiValue(value) => {
+            let level = 8 + 10 * (value - 232);
+            Some([level, level, level])
+        }
+        named => NAMED.iter().position(|c| *c == named).map(|i| NAMED_RGB[i]),
+    }
+}
+
+/// Replaces everything between `<!-- shketch:<marker> -->` and `<!-- /shketch:<marker> -->`
+/// in `document` with `diagram`, keeping the markers themselves.
+pub fn splice(document: &str, marker: &str, diagram: &str) -> crate::Result<String> {
+    let open = format!("<!-- shketch:{} -->", marker);
+    let close = format!("<!-- /shketch:{} -->", marker);
+
+    let start = match document.find(&open) {
+        Some(index) => index + open.len(),
+        None => return Err(format!("marker `{}` not found", open).into()),
+    };
+    let end = match document[start..].find(&close) {
+        Some(index) => start + index,
+        None => return Err(format!("marker `{}` not found", close).into()),
+    };
+
+    Ok(format!("{}\n{}{}", &document[..start], diagram, &document[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A segment with `rows` of text, one per line, starting at `origin`.
+    fn sketch(origin: (u16, u16), rows: &[&str]) -> grid::Segment {
+        let segments: Vec<grid::Segment> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let pos = grid::Point::new(origin.0, origin.1 + i as u16);
+                grid::Segment::from_str(pos, row, terminal::Format::default())
+            })
+            .collect();
+        segments.iter().sum()
+    }
+
+    #[test]
+    fn markdown_drops_blank_rows_at_the_top_and_bottom() {
+        let blueprint = sketch((1, 1), &["     ", "  +-+", "     ", "  +-+", "     ", "     "]);
+        assert_eq!(Markdown::new().render(blueprint), "+-+\n\n+-+\n");
+    }
+
+    #[test]
+    fn markdown_strips_the_shared_indent() {
+        let blueprint = sketch((1, 1), &["    a  ", "  b    ", "      c"]);
+        assert_eq!(Markdown::new().render(blueprint), "  a\nb\n    c\n");
+    }
+
+    #[test]
+    fn markdown_fence_without_a_language() {
+        let blueprint = sketch((1, 1), &["x"]);
+        assert_eq!(Markdown::new().fenced("").render(blueprint), "```\nx\n```\n");
+    }
+
+    #[test]
+    fn markdown_fence_with_a_language() {
+        let blueprint = sketch((1, 1), &["x"]);
+        assert_eq!(Markdown::new().fenced("text").render(blueprint), "```text\nx\n```\n");
+    }
+
+    #[test]
+    fn ansi_switches_format_only_where_it_changes() {
+        let half_block = terminal::Format::new(terminal::Color::Red, terminal::Color::Blue);
+        let mut pixel = grid::Segment::new();
+        pixel.set_format(half_block);
+        pixel.add(grid::Cell::new((3, 1).into(), '▀'));
+        let ansi = to_ansi(&[sketch((1, 1), &["ab"]), pixel]);
+        assert_eq!(
+            ansi,
+            format!("ab{format}▀{reset}\n", reset = terminal::RESET_FORMAT, format = half_block)
+        );
+        assert_eq!(half_block.to_string(), "\x1B[0m\x1B[48;5;9m\x1B[38;5;12m");
+    }
+
+    #[test]
+    fn png_paints_two_pixels_per_cell() {
+        let mut pixel = grid::Segment::new();
+        pixel.set_format(terminal::Format::new(terminal::Color::Red, terminal::Color::Blue));
+        pixel.add(grid::Cell::new((1, 1).into(), '▀'));
+        let mut text = sketch((2, 1), &["x "]);
+        text.set_format(terminal::Format::new(terminal::Color::Reset, terminal::Color::Green));
+
+        let png = to_png(&[pixel, text]).unwrap();
+        let (info, mut reader) = png::Decoder::new(&png[..]).read_info().unwrap();
+        assert_eq!((info.width, info.height), (3, 2));
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        let pixels: Vec<&[u8]> = data.chunks(4).collect();
+        let (blue, red, green, clear) =
+            ([0, 0, 255, 255], [255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 0, 0]);
+        assert_eq!(pixels, [&blue[..], &green, &clear, &red, &green, &clear]);
+    }
+
+    #[test]
+    fn svg_draws_each_cell_over_its_background() {
+        let mut blueprint = sketch((3, 2), &["a<"]);
+        blueprint.set_format(terminal::Format::new(terminal::Color::Blue, terminal::Color::Red));
+        let svg = to_svg(&[blueprint]);
+        assert!(
+            svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"20\" height=\"20\"")
+        );
+        assert!(
+            svg.contains("<rect x=\"10\" y=\"0\" width=\"10\" height=\"20\" fill=\"#0000ff\"/>")
+        );
+        assert!(svg.contains("<text x=\"0\" y=\"15\" fill=\"#ff0000\">a</text>"));
+        assert!(svg.contains("<text x=\"10\" y=\"15\" fill=\"#ff0000\">&lt;</text>"));
+    }
+
+    #[test]
+    fn svg_combines_text_decorations() {
+        let mut blueprint = sketch((1, 1), &["a"]);
+        let mut format = terminal::Format::default();
+        format.attributes.toggle(terminal::Attribute::Underline);
+        format.attributes.toggle(terminal::Attribute::Strikethrough);
+        blueprint.set_format(format);
+        let svg = to_svg(&[blueprint]);
+        assert!(svg.contains(
+            "<text x=\"0\" y=\"15\" fill=\"#000000\" \
+                              text-decoration=\"underline line-through\">a</text>"
+        ));
+        assert_eq!(svg.matches("text-decoration").count(), 1);
+    }
+
+    #[test]
+    fn splice_replaces_the_marked_region() {
+        let document = "# Doc\n<!-- shketch:a -->\nold\n<!-- /shketch:a -->\nend\n";
+        assert_eq!(
+            splice(document, "a", "new\n").unwrap(),
+            "# Doc\n<!-- shketch:a -->\nnew\n<!-- /shketch:a -->\nend\n"
+        );
+    }
+
+    #[test]
+    fn splice_fails_without_a_closing_marker() {
+        let document = "<!-- shketch:a -->\nold\n";
+        let error = splice(document, "a", "new\n").unwrap_err();
+        assert_eq!(error.to_string(), "marker `<!-- /shketch:a -->` not found");
+    }
+
+    #[test]
+    fn splice_fails_without_an_opening_marker() {
+        let error = splice("nothing here\n", "a", "new\n").unwrap_err();
+        assert_eq!(error.to_string(), "marker `<!-- shketch:a -->` not found");
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str;
+
+use terminal::{Key, KeyEvent, KeyModifier};
+
+use crate::canvas;
+
+/// Something a key can be bound to. Each action has a name, like `quit` or
+/// `select-tool:line`, used to bind it in the config file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Action {
+    Quit,
+    Clear,
+    Undo,
+    Save,
+    SaveAs,
+    Copy,
+    DeleteSelection,
+    BrushChar,
+    BrushSize,
+    ToggleBraille,
+    TogglePixels,
+    NewLayer,
+    PreviousLayer,
+    NextLayer,
+    LowerLayer,
+    RaiseLayer,
+    ToggleVisibility,
+    ToggleLock,
+    NextFgColor,
+    NextBgColor,
+    CycleTheme,
+    Help,
+    CommandLine,
+    ToggleSnap,
+    NewBuffer,
+    PreviousBuffer,
+    NextBuffer,
+    CopySelection,
+    Paste,
+    Minimap,
+    ToggleAttribute(terminal::Attribute),
+    SelectTool(canvas::Tool),
+}
+
+#[rustfmt::skip]
+static ACTIONS: [(&str, Action); 30] = [
+    ("quit", Action::Quit),
+    ("clear", Action::Clear),
+    ("undo", Action::Undo),
+    ("save", Action::Save),
+    ("save-as", Action::SaveAs),
+    ("copy", Action::Copy),
+    ("delete-selection", Action::DeleteSelection),
+    ("brush-char", Action::BrushChar),
+    ("brush-size", Action::BrushSize),
+    ("toggle-braille", Action::ToggleBraille),
+    ("toggle-pixels", Action::TogglePixels),
+    ("new-layer", Action::NewLayer),
+    ("previous-layer", Action::PreviousLayer),
+    ("next-layer", Action::NextLayer),
+    ("lower-layer", Action::LowerLayer),
+    ("raise-layer", Action::RaiseLayer),
+    ("toggle-visibility", Action::ToggleVisibility),
+    ("toggle-lock", Action::ToggleLock),
+    ("next-fg-color", Action::NextFgColor),
+    ("next-bg-color", Action::NextBgColor),
+    ("cycle-theme", Action::CycleTheme),
+    ("help", Action::Help),
+    ("command-line", Action::CommandLine),
+    ("toggle-snap", Action::ToggleSnap),
+    ("new-buffer", Action::NewBuffer),
+    ("previous-buffer", Action::PreviousBuffer),
+    ("next-buffer", Action::NextBuffer),
+    ("copy-selection", Action::CopySelection),
+    ("paste", Action::Paste),
+    ("minimap", Action::Minimap),
+];
+
+#[rustfmt::skip]
+static ATTRIBUTES: [(&str, terminal::Attribute); 6] = [
+    ("bold", terminal::Attribute::Bold),
+    ("dim", terminal::Attribute::Dim),
+    ("italic", terminal::Attribute::Italic),
+    ("underline", terminal::Attribute::Underline),
+    ("reverse", terminal::Attribute::Reverse),
+    ("strikethrough", terminal::Attribute::Strikethrough),
+];
+
+/// Headings the help overlay groups actions under, in the order they're shown.
+pub static CATEGORIES: [&str; 7] = ["File", "Edit", "Buffers", "Brush", "Layers", "Style", "Tools"];
+
+impl Action {
+    /// Every action, in the order they're listed in error messages.
+    pub fn all() -> Vec<Action> {
+        let mut actions: Vec<Action> = ACTIONS.iter().map(|(_, action)| *action).collect();
+        actions.extend(ATTRIBUTES.iter().map(|(_, attribute)| Action::ToggleAttribute(*attribute)));
+        actions.extend(canvas::Tool::all().map(Action::SelectTool));
+        actions
+    }
+
+    /// One of [`CATEGORIES`].
+    pub fn category(self) -> &'static str {
+        match self {
+            Action::Quit
+            | Action::Save
+            | Action::SaveAs
+            | Action::Copy
+            | Action::Help
+            | Action::CommandLine
+            | Action::Minimap => "File",
+            Action::Clear
+            | Action::Undo
+            | Action::DeleteSelection
+            | Action::CopySelection
+            | Action::Paste => "Edit",
+            Action::NewBuffer | Action::PreviousBuffer | Action::NextBuffer => "Buffers",
+            Action::BrushChar
+            | Action::BrushSize
+            | Action::ToggleBraille
+            | Action::TogglePixels
+            | Action::ToggleSnap => "Brush",
+            Action::NewLayer
+            | Action::PreviousLayer
+            | Action::NextLayer
+            | Action::LowerLayer
+            | Action::RaiseLayer
+            | Action::ToggleVisibility
+            | Action::ToggleLock => "Layers",
+            Action::NextFgColor
+            | Action::NextBgColor
+            | Action::CycleTheme
+            | Action::ToggleAttribute(_) => "Style",
+            Action::SelectTool(_) => "Tools",
+        }
+    }
+
+    pub fn description(self) -> String {
+        let description = match self {
+            Action::Quit => "Quit",
+            Action::Clear => "Clear the canvas",
+            Action::Undo => "Undo",
+            Action::Save => "Save",
+            Action::SaveAs => "Save as",
+            Action::Copy => "Copy to clipboard",
+            Action::DeleteSelection => "Delete selection",
+            Action::BrushChar => "Brush character",
+            Action::BrushSize => "Brush size",
+            Action::ToggleBraille => "Braille resolution",
+            Action::TogglePixels => "Pixel resolution",
+            Action::NewLayer => "New layer",
+            Action::PreviousLayer => "Previous layer",
+            Action::NextLayer => "Next layer",
+            Action::LowerLayer => "Move layer down",
+            Action::RaiseLayer => "Move layer up",
+            Action::ToggleVisibility => "Show or hide layer",
+            Action::ToggleLock => "Lock or unlock layer",
+            Action::NextFgColor => "Next foreground colour",
+            Action::NextBgColor => "Next background colour",
+            Action::CycleTheme => "Next theme",
+            Action::Help => "Help",
+            Action::CommandLine => "Command line",
+            Action::ToggleSnap => "Snap to grid and shapes",
+            Action::NewBuffer => "New sketch",
+            Action::PreviousBuffer => "Previous sketch",
+            Action::NextBuffer => "Next sketch",
+            Action::CopySelection => "Copy selection",
+            Action::Paste => "Paste selection",
+            Action::Minimap => "Overview of the whole sketch",
+            Action::ToggleAttribute(attribute) => {
+                let (name, _) =
+                    ATTRIBUTES.iter().find(|(_, a)| *a == attribute).expect("unnamed attribute");
+                return format!("Toggle {}", name);
+            }
+            Action::SelectTool(tool) => {
+                let name = tool.to_string();
+                return format!("{}{}", name[..1].to_uppercase(), &name[1..]);
+            }
+        };
+        description.to_string()
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::ToggleAttribute(attribute) => {
+                let (name, _) =
+                    ATTRIBUTES.iter().find(|(_, a)| a == attribute).expect("unnamed attribute");
+                write!(f, "toggle-attribute:{}", name)
+            }
+            Action::SelectTool(tool) => write!(f, "select-tool:{}", tool),
+            action => {
+                let (name, _) = ACTIONS.iter().find(|(_, a)| a == action).expect("unnamed action");
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+impl str::FromStr for Action {
+    type Err = crate::Error;
+
+    fn from_str(name: &str) -> crate::Result<Self> {
+        let action = match name.find(':') {
+            Some(index) => {
+                let arg = &name[index + 1..];
+                match &name[..index] {
+                    "toggle-attribute" => ATTRIBUTES
+                        .iter()
+                        .find(|(n, _)| *n == arg)
+                        .map(|(_, attribute)| Action::ToggleAttribute(*attribute)),
+                    "select-tool" => arg.parse().ok().map(Action::SelectTool),
+                    _ => None,
+                }
+            }
+            None => ACTIONS.iter().find(|(n, _)| *n == name).map(|(_, action)| *action),
+        };
+
+        action.ok_or_else(|| {
+            let names: Vec<String> = Action::all().iter().map(Action::to_string).collect();
+            format!("unknown action `{}`, expected one of: {}", name, names.join(", ")).into()
+        })
+    }
+}
+
+/// Bindings from key events to actions. Keys pressed with a modifier fall back to their
+/// plain binding when the combination itself isn't bound.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(KeyEvent, Action)>,
+}
+
+impl Keymap {
+    /// Applies `overrides`, key names mapped to action names, on top of the default bindings.
+    /// Binding a key to `none` unbinds it.
+    pub fn new(overrides: &BTreeMap<String, String>) -> crate::Result<Self> {
+        let mut keymap = Self::default();
+        let mut bindings = Vec::new();
+        for (key, action) in overrides {
+            let event: KeyEvent = key.parse()?;
+            keymap.bindings.retain(|(bound, _)| *bound != event);
+            bindings.retain(|(bound, _)| *bound != event);
+            if action != "none" {
+                let action = action.parse().map_err(|error| format!("key `{}`: {}", key, error))?;
+                bindings.push((event, action));
+            }
+        }
+
+        // Overrides come first so that they're the ones shown in the toolbar
+        bindings.append(&mut keymap.bindings);
+        keymap.bindings = bindings;
+        Ok(keymap)
+    }
+
+    pub fn action(&self, event: KeyEvent) -> Option<Action> {
+        let find = |event| self.bindings.iter().find(|(bound, _)| *bound == event);
+        find(event).or_else(|| find(KeyEvent::new(event.key, None))).map(|(_, action)| *action)
+    }
+
+    pub fn keys(&self, action: Action) -> impl Iterator<Item = KeyEvent> + '_ {
+        self.bindings.iter().filter(move |(_, a)| *a == action).map(|(event, _)| *event)
+    }
+
+    /// The first key bound to `action`, for display.
+    pub fn label(&self, action: Action) -> String {
+        self.keys(action).next().map_or_else(|| "-".to_string(), |event| event.to_string())
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let char = |char| KeyEvent::new(Key::Char(char), None);
+        let mut bindings = vec![
+            (char('q'), Action::Quit),
+            (char('k'), Action::Clear),
+            (char('u'), Action::Undo),
+            (KeyEvent::new(Key::Char('s'), Some(KeyModifier::Ctrl)), Action::Save),
+            (char('S'), Action::SaveAs),
+            (char('y'), Action::Copy),
+            (char('x'), Action::DeleteSelection),
+            (KeyEvent::new(Key::Backspace, None), Action::DeleteSelection),
+            (char('b'), Action::BrushChar),
+            (char('z'), Action::BrushSize),
+            (char('m'), Action::ToggleBraille),
+            (char('p'), Action::TogglePixels),
+            (char('n'), Action::NewLayer),
+            (char('['), Action::PreviousLayer),
+            (char(']'), Action::NextLayer),
+            (char('{'), Action::LowerLayer),
+            (char('}'), Action::RaiseLayer),
+            (char('v'), Action::ToggleVisibility),
+            (char('l'), Action::ToggleLock),
+            (char('c'), Action::NextFgColor),
+            (char('C'), Action::NextBgColor),
+            (char('T'), Action::CycleTheme),
+            (char('?'), Action::Help),
+            (KeyEvent::new(Key::F(1), None), Action::Help),
+            (char(':'), Action::CommandLine),
+            (char('g'), Action::ToggleSnap),
+            (char('N'), Action::NewBuffer),
+            (char('<'), Action::PreviousBuffer),
+            (char('>'), Action::NextBuffer),
+            (KeyEvent::new(Key::Char('c'), Some(KeyModifier::Ctrl)), Action::CopySelection),
+            (KeyEvent::new(Key::Char('v'), Some(KeyModifier::Ctrl)), Action::Paste),
+            (char('M'), Action::Minimap),
+        ];
+        bindings.extend(
+            "BDIURX"
+                .chars()
+                .zip(terminal::Attribute::ALL.iter())
+                .map(|(key, attribute)| (char(key), Action::ToggleAttribute(*attribute))),
+        );
+        bindings.extend(
+            "1234567890"
+                .chars()
+                .zip(canvas::Tool::all())
+                .map(|(key, tool)| (char(key), Action::SelectTool(tool))),
+        );
+
+        Self { bindings }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(overrides: &[(&str, &str)]) -> crate::Result<Keymap> {
+        let overrides =
+            overrides.iter().map(|(key, action)| (key.to_string(), action.to_string())).collect();
+        Keymap::new(&overrides)
+    }
+
+    fn key(name: &str) -> KeyEvent {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn overrides_bind_keys_and_come_first() {
+        let keymap = keymap(&[("w", "quit"), ("t", "select-tool:text")]).unwrap();
+        assert_eq!(keymap.action(key("w")), Some(Action::Quit));
+        assert_eq!(keymap.action(key("t")), Some(Action::SelectTool(canvas::Tool::Text)));
+        assert_eq!(keymap.label(Action::Quit), "w");
+        // The default binding still works
+        assert_eq!(keymap.action(key("q")), Some(Action::Quit));
+    }
+
+    #[test]
+    fn none_unbinds_a_key() {
+        let keymap = keymap(&[("q", "none")]).unwrap();
+        assert_eq!(keymap.action(key("q")), None);
+    }
+
+    #[test]
+    fn rebinding_a_key_replaces_its_default() {
+        let keymap = keymap(&[("u", "clear")]).unwrap();
+        assert_eq!(keymap.action(key("u")), Some(Action::Clear));
+        assert_eq!(keymap.label(Action::Undo), "-");
+    }
+
+    #[test]
+    fn ctrl_falls_back_to_the_plain_key() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.action(key("ctrl+u")), Some(Action::Undo));
+        assert_eq!(keymap.action(key("ctrl+s")), Some(Action::Save));
+    }
+
+    #[test]
+    fn unknown_actions_name_the_key_and_the_choices() {
+        let error = keymap(&[("w", "explode")]).unwrap_err().to_string();
+        assert!(error.starts_with("key `w`: unknown action `explode`, expected one of: quit, "));
+        assert!(error.contains("toggle-attribute:bold"));
+        assert!(error.contains("select-tool:line"));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let error = keymap(&[("hyper+w", "quit")]).unwrap_err();
+        assert_eq!(error.to_string(), "unknown key `hyper+w`");
+    }
+}
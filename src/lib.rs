@@ -6,5 +6,10 @@ pub(crate) type Result<T = ()> = result::Result<T, Error>;
 
 pub mod app;
 pub(crate) mod canvas;
-pub(crate) mod export;
+pub(crate) mod command;
+pub mod config;
+pub mod export;
+pub(crate) mod keymap;
 pub(crate) mod menu;
+pub(crate) mod recovery;
+pub(crate) mod theme;
@@ -1,6 +1,6 @@
 use std::panic;
 
-use shketch::app;
+use shketch::{app, config, export};
 
 fn main() {
     let directions = ["up", "down", "left", "right", "backward_diagonal", "forward_diagonal"];
@@ -27,7 +27,7 @@ ____/  /    /  /       \____   |  \_____/    /
                         .help("Cursor character for this direction")
                         .takes_value(true)
                         .validator(|c| {
-                            if c.len() > 1 {
+                            if c.chars().count() > 1 {
                                 Err("Cannot use more than 1 character per direction".into())
                             } else {
                                 Ok(())
@@ -36,28 +36,198 @@ ____/  /    /  /       \____   |  \_____/    /
                 })
                 .collect::<Vec<clap::Arg>>(),
         )
+        .arg(
+            clap::Arg::with_name("markdown")
+                .long("markdown")
+                .help("Save as Markdown, with trailing and leading whitespace trimmed"),
+        )
+        .arg(
+            clap::Arg::with_name("ansi")
+                .long("ansi")
+                .help("Save as text with colour escape codes")
+                .conflicts_with_all(&["markdown", "png", "svg"]),
+        )
+        .arg(
+            clap::Arg::with_name("png")
+                .long("png")
+                .help("Save as a PNG image, with two pixels per cell")
+                .conflicts_with_all(&["markdown", "ansi", "svg"]),
+        )
+        .arg(
+            clap::Arg::with_name("svg")
+                .long("svg")
+                .help("Save as an SVG image, with the text in a monospace font")
+                .conflicts_with_all(&["markdown", "ansi", "png"]),
+        )
+        .arg(
+            clap::Arg::with_name("fence")
+                .long("fence")
+                .value_name("lang")
+                .help("Wrap Markdown output in a code fence, optionally tagged with a language")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("embed")
+                .long("embed")
+                .value_name("file")
+                .help("Save into the `<!-- shketch:<marker> -->` region of a Markdown file")
+                .takes_value(true)
+                .requires("marker"),
+        )
+        .arg(
+            clap::Arg::with_name("marker")
+                .long("marker")
+                .value_name("name")
+                .help("Marker name of the region to save into")
+                .takes_value(true)
+                .requires("embed"),
+        )
+        .arg(
+            clap::Arg::with_name("out_dir")
+                .long("out-dir")
+                .value_name("dir")
+                .help("Directory to save new sketches in")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("file_name")
+                .long("file-name")
+                .value_name("template")
+                .help("Name of new sketches, with `{timestamp}` and `{ext}` filled in")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("tool")
+                .long("tool")
+                .value_name("name")
+                .help("Tool to start with")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("theme")
+                .long("theme")
+                .value_name("name")
+                .help("Colours of the toolbar and prompts: dark, light, high-contrast or your own")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("page")
+                .long("page")
+                .value_name("size")
+                .help("Size of the page to sketch in, like 80x24, or just a width like 72")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("guides")
+                .long("guides")
+                .value_name("cells")
+                .help("Cells between dotted guide lines, or 0 to hide them")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("autosave_interval")
+                .long("autosave-interval")
+                .value_name("seconds")
+                .help("Seconds between saves to the recovery file, or 0 to turn them off")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("visible_only")
+                .long("visible-only")
+                .help("Only save layers that are visible"),
+        )
+        .arg(
+            clap::Arg::with_name("files")
+                .value_name("file")
+                .help("Text files to open, each in its own tab")
+                .multiple(true),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("config")
+                .about("Shows where the config file is read from")
+                .arg(
+                    clap::Arg::with_name("print").long("print").help(
+                        "Prints the effective settings, after environment variables and flags",
+                    ),
+                ),
+        )
         .get_matches();
 
-    let char_set = {
-        let mut set = terminal::grid::CharSet::default();
-        directions.iter().for_each(|direction| {
+    let config = config::Config::load().and_then(|mut config| {
+        for direction in directions.iter() {
             if let Some(value) = matches.value_of(direction) {
-                *(match *direction {
-                    "up" => &mut set.up,
-                    "down" => &mut set.down,
-                    "left" => &mut set.left,
-                    "right" => &mut set.right,
-                    "backward_diagonal" => &mut set.backward_diagonal,
-                    "forward_diagonal" => &mut set.forward_diagonal,
-                    _ => unreachable!(),
-                }) = value.as_bytes()[0] as char;
+                config.set(&format!("charset.{}", direction), value)?;
+            }
+        }
+        for (arg, key) in [
+            ("out_dir", "save_dir"),
+            ("file_name", "file_name"),
+            ("tool", "tool"),
+            ("theme", "theme"),
+            ("page", "page"),
+            ("guides", "guides"),
+            ("autosave_interval", "autosave_interval"),
+        ]
+        .iter()
+        {
+            if let Some(value) = matches.value_of(arg) {
+                config
+                    .set(key, value)
+                    .map_err(|error| format!("--{}: {}", arg.replace('_', "-"), error))?;
+            }
+        }
+        Ok(config)
+    });
+    let config = match config {
+        Ok(config) => config,
+        Err(error) => return eprintln!("{}", error),
+    };
+
+    if let Some(matches) = matches.subcommand_matches("config") {
+        if matches.is_present("print") {
+            match config.themes().and_then(|_| config.to_toml()) {
+                Ok(toml) => print!("{}", toml),
+                Err(error) => eprintln!("{}", error),
             }
-        });
-        set
+        } else if let Some(path) = config::file_path() {
+            println!("{}", path.display());
+        }
+        return;
+    }
+
+    let export_mode = {
+        let mut markdown = export::Markdown::new();
+        if matches.is_present("fence") {
+            markdown = markdown.fenced(matches.value_of("fence").unwrap_or(""));
+        }
+
+        match (matches.value_of("embed"), matches.value_of("marker")) {
+            (Some(file_name), Some(marker)) => export::Mode::Embed {
+                markdown,
+                file_name: file_name.to_string(),
+                marker: marker.to_string(),
+            },
+            _ if matches.is_present("markdown") || matches.is_present("fence") => {
+                export::Mode::Markdown(markdown)
+            }
+            _ if matches.is_present("ansi") => export::Mode::Ansi,
+            _ if matches.is_present("png") => export::Mode::Png,
+            _ if matches.is_present("svg") => export::Mode::Svg,
+            _ => export::Mode::Text,
+        }
     };
 
+    let files: Vec<String> =
+        matches.values_of("files").map_or_else(Vec::new, |files| files.map(String::from).collect());
     let result = panic::catch_unwind(|| {
-        if let Err(error) = app::launch(app::Opts::new(char_set)) {
+        if let Err(error) = app::launch(app::Opts::new(
+            config,
+            export_mode,
+            matches.is_present("visible_only"),
+            files.clone(),
+        )) {
             eprintln!("{}", error);
         }
     });
@@ -1,63 +1,1019 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::fs;
+use std::path;
+use std::time;
 
 use terminal::grid;
 
 use crate::canvas;
+use crate::keymap::{self, Action};
+use crate::theme;
 
-#[rustfmt::skip]
-static HIGHLIGHT_FORMAT: terminal::Format = terminal::Format {
-    bg_color: terminal::Color::White,
-    fg_color: terminal::Color::Black,
-};
+pub static PALETTE: [terminal::Color; 8] = [
+    terminal::Color::Reset,
+    terminal::Color::Red,
+    terminal::Color::Green,
+    terminal::Color::Yellow,
+    terminal::Color::Blue,
+    terminal::Color::Magenta,
+    terminal::Color::Cyan,
+    terminal::Color::White,
+];
 
 pub struct ToolBar {
     actions: grid::Segment,
     tools: HashMap<canvas::Tool, grid::Segment>,
+    status: grid::Segment,
+    layer: grid::Segment,
+    brush: grid::Segment,
+    resolutions: HashMap<canvas::Resolution, grid::Segment>,
+    palette: Vec<grid::Segment>,
+    keymap: keymap::Keymap,
+    theme: theme::Theme,
+    tabs: Vec<grid::Segment>,
+    /// Clickable labels and the actions they trigger.
+    entries: Vec<(Action, grid::Segment)>,
+    hovered: Option<usize>,
+    width: u16,
+    /// Columns of the foreground and background palettes and of the attributes, where they fit.
+    palette_columns: [Option<u16>; 3],
+    /// Whether the palettes are squeezed, without keys in their labels.
+    compact: bool,
+    layer_width: u16,
+    /// Column of the brush settings, after the brush tool's label.
+    brush_column: Option<u16>,
 }
 
 impl ToolBar {
-    pub fn new() -> Self {
-        let actions: grid::Segment = vec![
-            str_to_segment((1, 1), "Exit (q)"),
-            str_to_segment((15, 1), "Clear (k)"),
-            str_to_segment((30, 1), "Undo (u)"),
-            str_to_segment((45, 1), "Save (Ctrl+s)"),
+    /// Number of rows at the top of the screen taken up by the toolbar.
+    pub const HEIGHT: u16 = 5;
+
+    /// Lays out the toolbar along rows `width` columns wide. Labels lose their keys when a row
+    /// would be too long with them, and anything that still doesn't fit is left out.
+    pub fn new(keymap: &keymap::Keymap, theme: theme::Theme, width: u16) -> Self {
+        let label = |name: &str, action| format!("{} ({})", name, keymap.label(action));
+        let format = theme.toolbar.format();
+        let actions_row = [
+            ("Exit", Action::Quit),
+            ("Clear", Action::Clear),
+            ("Undo", Action::Undo),
+            ("Save", Action::Save),
+            ("Save as", Action::SaveAs),
+            ("Copy", Action::Copy),
+            ("Theme", Action::CycleTheme),
+        ];
+        let mut entries: Vec<(Action, grid::Segment)> = label_row(&actions_row, keymap, width)
+            .into_iter()
+            .map(|(x, text, action)| (action, str_to_segment((x, 1), &text, format)))
+            .collect();
+        let mut actions: grid::Segment = entries.iter().map(|(_, segment)| segment).sum();
+        actions.set_format(format);
+
+        let tools_row: Vec<(&str, Action)> = [
+            ("Plot", canvas::Tool::Plot),
+            ("Line", canvas::Tool::Line),
+            ("Erase", canvas::Tool::Erase),
+            ("Pick", canvas::Tool::Pick),
+            ("Rect", canvas::Tool::Rectangle),
+            ("Arrow", canvas::Tool::Arrow),
+            ("Text", canvas::Tool::Text),
+            ("Edit", canvas::Tool::Edit),
+            ("Ellipse", canvas::Tool::Ellipse),
         ]
         .iter()
-        .sum();
-
+        .map(|(name, tool)| (*name, Action::SelectTool(*tool)))
+        .collect();
         let mut tools: HashMap<canvas::Tool, grid::Segment> = HashMap::new();
-        tools.insert(canvas::Tool::Plot, str_to_segment((1, 2), "Plot (1)"));
-        tools.insert(canvas::Tool::Line, str_to_segment((15, 2), "Line (2)"));
-        tools.insert(canvas::Tool::Erase, str_to_segment((30, 2), "Erase (3)"));
+        for (x, text, action) in label_row(&tools_row, keymap, width) {
+            let segment = str_to_segment((x, 2), &text, format);
+            entries.push((action, segment.clone()));
+            if let Action::SelectTool(tool) = action {
+                tools.insert(tool, segment);
+            }
+        }
+
+        // The third row, after the modification status: both palettes, the attributes and the
+        // resolutions
+        let third_row = |compact: bool| {
+            let name =
+                |name: &str, action| if compact { name.to_string() } else { label(name, action) };
+            [
+                name("Fg", Action::NextFgColor),
+                name("Bg", Action::NextBgColor),
+                name("Braille", Action::ToggleBraille),
+                name("Pixels", Action::TogglePixels),
+            ]
+        };
+        let attributes_width = attribute_row(0, Default::default(), keymap, &theme)
+            .iter()
+            .map(|segment| segment.cells().len() as u16 + 1)
+            .sum::<u16>()
+            .saturating_sub(1);
+        let widths = |compact: bool| {
+            let [fg, bg, braille, pixels] = third_row(compact);
+            vec![
+                10,
+                palette_width(&fg, compact),
+                palette_width(&bg, compact),
+                attributes_width,
+                grid::text_width(&braille) as u16,
+                grid::text_width(&pixels) as u16,
+            ]
+        };
+        let compact = flow(&widths(false), width).contains(&None);
+        let columns = flow(&widths(compact), width);
+        let [fg, bg, braille, pixels] = third_row(compact);
+        let palette_columns = [columns[1], columns[2], columns[3]];
+        for (column, text, action) in [
+            (columns[1], &fg, Action::NextFgColor),
+            (columns[2], &bg, Action::NextBgColor),
+            (columns[4], &braille, Action::ToggleBraille),
+            (columns[5], &pixels, Action::TogglePixels),
+        ] {
+            if let Some(x) = column {
+                entries.push((action, str_to_segment((x, 3), text, format)));
+            }
+        }
+        if let Some(x) = columns[3] {
+            let attributes = terminal::Attribute::ALL
+                .iter()
+                .map(|attribute| Action::ToggleAttribute(*attribute));
+            entries.extend(attributes.zip(attribute_row(x, Default::default(), keymap, &theme)));
+        }
+        let mut resolutions = HashMap::new();
+        for (column, text, resolution) in [
+            (columns[4], braille, canvas::Resolution::Braille),
+            (columns[5], pixels, canvas::Resolution::Pixel),
+        ] {
+            if let Some(x) = column {
+                resolutions.insert(resolution, str_to_segment((x, 3), &text, format));
+            }
+        }
+
+        // The fourth row: the layer, then the brush tool and its settings on the right, if
+        // there's enough room for the layer
+        let brush = label("Brush", Action::SelectTool(canvas::Tool::Brush));
+        let brush_width = grid::text_width(&brush) as u16 + 1 + Self::BRUSH_WIDTH;
+        let (layer_width, brush_column) = match width.saturating_sub(brush_width + 2).min(50) {
+            layer_width if layer_width >= 20 => {
+                let x = layer_width + 3;
+                let segment = str_to_segment((x, 4), &brush, format);
+                entries.push((Action::SelectTool(canvas::Tool::Brush), segment.clone()));
+                tools.insert(canvas::Tool::Brush, segment);
+                (layer_width, Some(x + grid::text_width(&brush) as u16 + 1))
+            }
+            _ => (width, None),
+        };
 
-        let mut toolbar = Self { actions, tools };
+        let mut toolbar = Self {
+            actions,
+            tools,
+            status: grid::Segment::new(),
+            layer: grid::Segment::new(),
+            brush: grid::Segment::new(),
+            resolutions,
+            palette: Vec::new(),
+            tabs: Vec::new(),
+            keymap: keymap.clone(),
+            theme,
+            entries,
+            hovered: None,
+            width,
+            palette_columns,
+            compact,
+            layer_width,
+            brush_column,
+        };
         toolbar.highlight_tool(Default::default());
+        toolbar.highlight_format(Default::default());
+        toolbar.set_brush(Default::default());
+        toolbar.set_resolution(Default::default());
         toolbar
     }
 
+    /// Columns taken up by the brush settings.
+    const BRUSH_WIDTH: u16 = 20;
+
     pub fn highlight_tool(&mut self, tool: canvas::Tool) {
         for (menu_tool, segment) in &mut self.tools {
             if *menu_tool == tool {
-                segment.set_format(HIGHLIGHT_FORMAT);
+                segment.set_format(self.theme.highlight.format());
+            } else {
+                segment.set_format(self.theme.toolbar.format());
+            }
+        }
+    }
+
+    pub fn highlight_format(&mut self, format: terminal::Format) {
+        let label = |name: &str, action| match self.compact {
+            true => name.to_string(),
+            false => format!("{} ({})", name, self.keymap.label(action)),
+        };
+        let (fg_label, bg_label) =
+            (label("Fg", Action::NextFgColor), label("Bg", Action::NextBgColor));
+        let [fg, bg, attributes] = self.palette_columns;
+        let mut palette = Vec::new();
+        if let Some(x) = fg {
+            palette.append(&mut palette_row(
+                x,
+                &fg_label,
+                format.fg_color,
+                self.compact,
+                &self.theme,
+            ));
+        }
+        if let Some(x) = bg {
+            palette.append(&mut palette_row(
+                x,
+                &bg_label,
+                format.bg_color,
+                self.compact,
+                &self.theme,
+            ));
+        }
+        if let Some(x) = attributes {
+            palette.append(&mut attribute_row(x, format.attributes, &self.keymap, &self.theme));
+        }
+        self.palette = palette;
+    }
+
+    pub fn set_layer(&mut self, layer: &canvas::Layer, (index, count): (usize, usize)) {
+        let mut text = format!(
+            "Layer {}/{} ({} {}): {}",
+            index,
+            count,
+            self.keymap.label(Action::PreviousLayer),
+            self.keymap.label(Action::NextLayer),
+            layer.name
+        );
+        if !layer.visible {
+            text.push_str(" (hidden)");
+        }
+        if layer.locked {
+            text.push_str(" (locked)");
+        }
+        let text = fit(text, self.layer_width as usize);
+        self.layer = str_to_segment((1, 4), &text, self.theme.status.format());
+    }
+
+    pub fn set_resolution(&mut self, resolution: canvas::Resolution) {
+        for (menu_resolution, segment) in &mut self.resolutions {
+            if *menu_resolution == resolution {
+                segment.set_format(self.theme.highlight.format());
             } else {
-                segment.set_format(Default::default());
+                segment.set_format(self.theme.toolbar.format());
             }
         }
     }
+
+    pub fn set_brush(&mut self, brush: canvas::Brush) {
+        let x = match self.brush_column {
+            Some(x) => x,
+            None => return,
+        };
+        let text = format!(
+            "'{}' ({}) {} ({})",
+            brush.char,
+            self.keymap.label(Action::BrushChar),
+            brush.size,
+            self.keymap.label(Action::BrushSize)
+        );
+        let text = fit(text, Self::BRUSH_WIDTH.min(self.width.saturating_sub(x - 1)) as usize);
+        self.brush = str_to_segment((x, 4), &text, self.theme.status.format());
+    }
+
+    /// Shows a tab for each open sketch, given its name and whether it's been modified, with
+    /// the one at `current` highlighted. Tabs past the right edge are left out.
+    pub fn set_buffers(&mut self, buffers: &[(String, bool)], current: usize) {
+        let label = format!(
+            "Sketches ({} {}):",
+            self.keymap.label(Action::PreviousBuffer),
+            self.keymap.label(Action::NextBuffer)
+        );
+        let mut texts = vec![label];
+        texts.extend(buffers.iter().enumerate().map(|(index, (name, modified))| {
+            format!(" {} {}{} ", index + 1, name, if *modified { " *" } else { "" })
+        }));
+        let widths: Vec<u16> = texts.iter().map(|text| grid::text_width(text) as u16).collect();
+        self.tabs = flow(&widths, self.width)
+            .into_iter()
+            .zip(texts)
+            .enumerate()
+            .filter_map(|(index, (column, text))| {
+                let style = match index {
+                    0 => self.theme.status,
+                    index if index - 1 == current => self.theme.highlight,
+                    _ => self.theme.toolbar,
+                };
+                Some(str_to_segment((column?, 5), &text, style.format()))
+            })
+            .collect();
+    }
+
+    /// The action of the label at `pos`, if any.
+    pub fn action_at(&self, pos: grid::Point) -> Option<Action> {
+        self.entry_at(pos).map(|index| self.entries[index].0)
+    }
+
+    /// Highlights the label at `pos`, or stops highlighting when `None`. The terminal only
+    /// reports the mouse moving while a button is held, so this follows presses and drags.
+    pub fn hover(&mut self, pos: Option<grid::Point>) {
+        self.hovered = pos.and_then(|pos| self.entry_at(pos));
+    }
+
+    fn entry_at(&self, pos: grid::Point) -> Option<usize> {
+        self.entries.iter().position(|(_, segment)| match segment.boundaries() {
+            Some((start, end)) => {
+                (start.x..=end.x).contains(&pos.x) && (start.y..=end.y).contains(&pos.y)
+            }
+            None => false,
+        })
+    }
+
+    pub fn set_modified(&mut self, modified: bool) {
+        let text = if modified { "* Modified" } else { "          " };
+        self.status = str_to_segment((1, 3), text, self.theme.status.format());
+    }
+}
+
+/// Places items of the given widths left to right from the first column, two columns apart,
+/// or one if that's what it takes to fit them in `width`. Those that still don't fit get no
+/// column, along with everything after them.
+fn flow(widths: &[u16], width: u16) -> Vec<Option<u16>> {
+    let total =
+        |gap: u16| widths.iter().sum::<u16>() + gap * (widths.len() as u16).saturating_sub(1);
+    let gap = if total(2) <= width { 2 } else { 1 };
+    let mut x = 1;
+    let mut fits = true;
+    widths
+        .iter()
+        .map(|item| {
+            fits = fits && x + item - 1 <= width;
+            let column = if fits { Some(x) } else { None };
+            x += item + gap;
+            column
+        })
+        .collect()
+}
+
+/// Clickable labels for `items` along a row `width` columns wide, with their keys if they all
+/// fit that way, and their columns.
+fn label_row(
+    items: &[(&str, Action)],
+    keymap: &keymap::Keymap,
+    width: u16,
+) -> Vec<(u16, String, Action)> {
+    let full: Vec<String> = items
+        .iter()
+        .map(|(name, action)| format!("{} ({})", name, keymap.label(*action)))
+        .collect();
+    let widths = |labels: &[String]| -> Vec<u16> {
+        labels.iter().map(|label| grid::text_width(label) as u16).collect()
+    };
+    let labels = match flow(&widths(&full), width).contains(&None) {
+        true => items.iter().map(|(name, _)| name.to_string()).collect(),
+        false => full,
+    };
+    flow(&widths(&labels), width)
+        .into_iter()
+        .zip(labels)
+        .zip(items)
+        .filter_map(|((column, label), (_, action))| Some((column?, label, *action)))
+        .collect()
 }
 
 impl fmt::Display for ToolBar {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.actions)?;
-        for segment in self.tools.values() {
+        write!(f, "{}{}{}{}", self.actions, self.status, self.layer, self.brush)?;
+        let segments = self.tools.values().chain(self.resolutions.values()).chain(&self.palette);
+        for segment in segments.chain(&self.tabs) {
+            write!(f, "{}", segment)?;
+        }
+        if let Some(index) = self.hovered {
+            let mut segment = self.entries[index].1.clone();
+            let format = self.theme.highlight.format();
+            segment.set_format(format.with_attributes(terminal::Attribute::Underline.into()));
             write!(f, "{}", segment)?;
         }
         Ok(())
     }
 }
 
-fn str_to_segment((x, y): (u16, u16), text: &str) -> grid::Segment {
-    grid::Segment::from_str(grid::Point::new(x, y), text, Default::default())
+/// The bottom row of the screen, describing the current tool, where the mouse is on the
+/// canvas, the size of the selection and the state of the file, along with short lived messages.
+pub struct StatusBar {
+    row: u16,
+    width: u16,
+    theme: theme::Theme,
+    tool: String,
+    cursor: Option<grid::Point>,
+    selection: Option<(u16, u16)>,
+    snap: bool,
+    file: String,
+    message: Option<(String, time::Instant)>,
+}
+
+impl StatusBar {
+    /// How long messages stay up for.
+    pub const MESSAGE_DURATION: time::Duration = time::Duration::from_secs(3);
+
+    pub fn new(row: u16, width: u16, theme: theme::Theme) -> Self {
+        Self {
+            row,
+            width,
+            theme,
+            tool: String::new(),
+            cursor: None,
+            selection: None,
+            snap: false,
+            file: String::new(),
+            message: None,
+        }
+    }
+
+    pub fn set_theme(&mut self, theme: theme::Theme) {
+        self.theme = theme;
+    }
+
+    pub fn set_tool(&mut self, tool: canvas::Tool, brush: canvas::Brush) {
+        self.tool = format!("{} '{}'", tool, brush.char);
+    }
+
+    /// Records the position of the mouse, given in screen coordinates.
+    pub fn set_cursor(&mut self, pos: grid::Point) {
+        self.cursor = Some(pos);
+    }
+
+    pub fn set_selection(&mut self, size: Option<(u16, u16)>) {
+        self.selection = size;
+    }
+
+    pub fn set_snap(&mut self, snap: bool) {
+        self.snap = snap;
+    }
+
+    pub fn set_file(&mut self, file_name: Option<&str>, modified: bool) {
+        self.file = format!("{}{}", file_name.unwrap_or("[new]"), if modified { " *" } else { "" });
+    }
+
+    pub fn notify(&mut self, message: &str) {
+        self.message = Some((message.to_string(), time::Instant::now()));
+    }
+
+    fn segments(&self) -> Vec<grid::Segment> {
+        let mut text = format!(" {}", self.tool);
+        if let Some(pos) = self.cursor {
+            // Relative to the top left corner of the canvas, below the toolbar
+            let x = pos.x.saturating_sub(1);
+            let y = pos.y.saturating_sub(ToolBar::HEIGHT + 1);
+            text.push_str(&format!(" | {},{}", x, y));
+        }
+        if let Some((width, height)) = self.selection {
+            text.push_str(&format!(" | {}x{}", width, height));
+        }
+        if self.snap {
+            text.push_str(" | snap");
+        }
+        text.push_str(&format!(" | {} ", self.file));
+
+        let mut segments = vec![grid::Segment::from_str(
+            grid::Point::new(1, self.row),
+            &text,
+            self.theme.status.format(),
+        )];
+        let mut x = 1 + grid::text_width(&text) as u16;
+        match &self.message {
+            Some((message, shown)) if shown.elapsed() < Self::MESSAGE_DURATION => {
+                let message = format!(" {} ", message);
+                segments.push(grid::Segment::from_str(
+                    grid::Point::new(x, self.row),
+                    &message,
+                    self.theme.banner.format(),
+                ));
+                x += grid::text_width(&message) as u16;
+            }
+            _ => {}
+        }
+        let padding = self.width.saturating_sub(x - 1) as usize;
+        segments.push(grid::Segment::from_str(
+            grid::Point::new(x, self.row),
+            &" ".repeat(padding),
+            self.theme.status.format(),
+        ));
+        segments
+    }
+}
+
+impl fmt::Display for StatusBar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.segments().iter().try_for_each(|segment| write!(f, "{}", segment))
+    }
+}
+
+/// The right and bottom edges of the page between `start` and `end`, where there's room for
+/// them in `area`, and dotted lines every `guides` cells across the page, or across `area`
+/// when there's no page.
+pub fn page_frame(
+    page: Option<(grid::Point, grid::Point)>,
+    area: (grid::Point, grid::Point),
+    guides: u16,
+    theme: &theme::Theme,
+) -> Vec<grid::Segment> {
+    let (start, end) = page.unwrap_or(area);
+    let end = grid::Point::new(end.x.min(area.1.x), end.y.min(area.1.y));
+
+    let mut dots = grid::Segment::new();
+    dots.set_format(theme.status.format().with_attributes(terminal::Attribute::Dim.into()));
+    if guides > 0 {
+        for y in start.y..=end.y {
+            for x in start.x..=end.x {
+                let (column, row) = (x - start.x, y - start.y);
+                if (column > 0 && column.is_multiple_of(guides))
+                    || (row > 0 && row.is_multiple_of(guides))
+                {
+                    dots.add(grid::Cell::new(grid::Point::new(x, y), '·'));
+                }
+            }
+        }
+    }
+
+    let mut border = grid::Segment::new();
+    border.set_format(theme.status.format());
+    if let Some((_, page_end)) = page {
+        let right = page_end.x < area.1.x;
+        let bottom = page_end.y < area.1.y;
+        if right {
+            (start.y..=end.y).for_each(|y| border.add(grid::Cell::new((end.x + 1, y).into(), '│')));
+        }
+        if bottom {
+            (start.x..=end.x).for_each(|x| border.add(grid::Cell::new((x, end.y + 1).into(), '─')));
+        }
+        if right && bottom {
+            border.add(grid::Cell::new((end.x + 1, end.y + 1).into(), '┘'));
+        }
+    }
+
+    vec![dots, border]
+}
+
+/// Dotted lines across `area` through each of `points` that lines up with a column or row
+/// from `alignment`.
+pub fn alignment_guides(
+    points: &[grid::Point],
+    (columns, rows): &(Vec<u16>, Vec<u16>),
+    (start, end): (grid::Point, grid::Point),
+    theme: &theme::Theme,
+) -> grid::Segment {
+    let mut segment = grid::Segment::new();
+    segment.set_format(theme.selection.format());
+    for point in points {
+        if columns.contains(&point.x) {
+            (start.y..=end.y).for_each(|y| segment.add(grid::Cell::new((point.x, y).into(), '┊')));
+        }
+        if rows.contains(&point.y) {
+            (start.x..=end.x).for_each(|x| segment.add(grid::Cell::new((x, point.y).into(), '┄')));
+        }
+    }
+    segment
+}
+
+/// A panel over the canvas listing every action and the keys bound to it, by category. When
+/// the columns don't all fit across the screen, they're split into pages, turned with Tab.
+pub struct Help {
+    /// Each entry is the text and whether it's a heading
+    columns: Vec<Vec<(String, bool)>>,
+    column_width: u16,
+    /// Columns on each page
+    per_page: usize,
+    page: usize,
+    theme: theme::Theme,
+    size: (u16, u16),
+    segments: Vec<grid::Segment>,
+}
+
+impl Help {
+    pub fn new(keymap: &keymap::Keymap, theme: theme::Theme, (width, height): (u16, u16)) -> Self {
+        let mut lines: Vec<Vec<(String, bool)>> = Vec::new();
+        for category in keymap::CATEGORIES.iter() {
+            let mut block = vec![(category.to_string(), true)];
+            for action in Action::all().into_iter().filter(|a| a.category() == *category) {
+                let keys: Vec<String> = keymap.keys(action).map(|key| key.to_string()).collect();
+                let keys = if keys.is_empty() { "-".to_string() } else { keys.join(", ") };
+                block.push((format!("{} {}", pad(keys, 12), action.description()), false));
+            }
+            lines.push(block);
+        }
+
+        // Categories flow down columns as tall as the screen allows, leaving room for the
+        // border and the title
+        let rows = height.saturating_sub(ToolBar::HEIGHT + 4).max(1) as usize;
+        let mut columns: Vec<Vec<(String, bool)>> = vec![Vec::new()];
+        for block in lines {
+            let column = columns.last_mut().expect("at least one column");
+            if !column.is_empty() {
+                if column.len() + 1 + block.len() > rows {
+                    columns.push(Vec::new());
+                } else {
+                    column.push((String::new(), false));
+                }
+            }
+            for line in block {
+                if columns.last().expect("at least one column").len() >= rows {
+                    columns.push(Vec::new());
+                }
+                columns.last_mut().expect("at least one column").push(line);
+            }
+        }
+
+        // As many columns to a page as fit between the borders, cutting the longest
+        // descriptions short by up to a quarter when that fits in another
+        let room = width.saturating_sub(3).max(1);
+        let widest =
+            columns.iter().flatten().map(|(text, _)| grid::text_width(text)).max().unwrap_or(0);
+        let natural = (widest as u16 + 2).min(room);
+        let per_page = (room / (natural * 3 / 4).max(1)).clamp(1, columns.len() as u16);
+        let column_width = (room / per_page).min(natural);
+        let per_page = per_page as usize;
+
+        let mut help = Self {
+            columns,
+            column_width,
+            per_page,
+            page: 0,
+            theme,
+            size: (width, height),
+            segments: Vec::new(),
+        };
+        help.layout();
+        help
+    }
+
+    /// Shows the next page of columns, or the first after the last.
+    pub fn next_page(&mut self) {
+        self.page = (self.page + 1) % self.pages();
+        self.layout();
+    }
+
+    fn pages(&self) -> usize {
+        self.columns.len().div_ceil(self.per_page)
+    }
+
+    fn layout(&mut self) {
+        let (width, height) = self.size;
+        let column_width = self.column_width;
+        let columns: Vec<&Vec<(String, bool)>> =
+            self.columns.iter().skip(self.page * self.per_page).take(self.per_page).collect();
+        let rows = columns.iter().map(|column| column.len()).max().unwrap_or(0) as u16;
+        let inner_width = column_width * columns.len() as u16 + 1;
+        let x = 1 + width.saturating_sub(inner_width + 2) / 2;
+        let y = ToolBar::HEIGHT + 1 + height.saturating_sub(ToolBar::HEIGHT + rows + 2) / 2;
+
+        let format = self.theme.toolbar.format();
+        let heading = format.with_attributes(terminal::Attribute::Bold.into());
+        let title = match self.pages() {
+            1 => " Help (Esc to close) ".to_string(),
+            pages => format!(" Help {}/{} (Tab for more, Esc to close) ", self.page + 1, pages),
+        };
+        let title_width = grid::text_width(&title).min(inner_width as usize - 1);
+        let title = fit(title, title_width);
+        let rule = "─".repeat((inner_width as usize).saturating_sub(grid::text_width(&title) + 1));
+        let mut segments = vec![
+            str_to_segment((x, y), "┌─", format),
+            str_to_segment((x + 2, y), &title, self.theme.highlight.format()),
+            str_to_segment((x + 2 + grid::text_width(&title) as u16, y), &rule, format),
+            str_to_segment((x + 1 + inner_width, y), "┐", format),
+        ];
+        for row in 0..rows {
+            let y = y + 1 + row;
+            segments.push(str_to_segment((x, y), "│ ", format));
+            for (index, column) in columns.iter().enumerate() {
+                let (text, is_heading) = column.get(row as usize).cloned().unwrap_or_default();
+                segments.push(str_to_segment(
+                    (x + 2 + column_width * index as u16, y),
+                    &pad(fit(text, column_width.saturating_sub(2) as usize), column_width as usize),
+                    if is_heading { heading } else { format },
+                ));
+            }
+            segments.push(str_to_segment((x + 1 + inner_width, y), "│", format));
+        }
+        let bottom = format!("└{}┘", "─".repeat(inner_width as usize));
+        segments.push(str_to_segment((x, y + 1 + rows), &bottom, format));
+        self.segments = segments;
+    }
+
+    pub fn segment(&self) -> grid::Segment {
+        self.segments.iter().sum()
+    }
+}
+
+impl fmt::Display for Help {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.segments.iter().try_for_each(|segment| write!(f, "{}", segment))
+    }
+}
+
+/// Shades from nothing to fully drawn on.
+static DENSITY: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// A zoomed out view of the whole sketch in the bottom right corner, with the part on screen
+/// highlighted. Each of its cells covers a block of at least 4 by 2 canvas cells, shaded by how
+/// much of the block is drawn on. It reaches a screen past the sketch to the right and below,
+/// so that there's always somewhere new to jump to.
+pub struct Minimap {
+    segments: Vec<grid::Segment>,
+    /// Corners of the minimap on screen, border included
+    bounds: (grid::Point, grid::Point),
+    /// The canvas cell at the top left of the map
+    start: grid::Point,
+    /// Canvas columns and rows covered by each cell of the map
+    block: (u16, u16),
+}
+
+impl Minimap {
+    pub fn new(
+        sketch: &[grid::Segment],
+        viewport: (grid::Point, grid::Point),
+        theme: theme::Theme,
+        (width, height): (u16, u16),
+    ) -> Self {
+        // Blank cells still count towards the extent, so a page shows up in full
+        let cells = || sketch.iter().flat_map(|segment| segment.cells());
+        let (start, end) = cells().fold(viewport, |(start, end), cell| {
+            let pos = cell.pos();
+            (
+                grid::Point::new(start.x.min(pos.x), start.y.min(pos.y)),
+                grid::Point::new(end.x.max(pos.x), end.y.max(pos.y)),
+            )
+        });
+        let end = grid::Point::new(
+            end.x.saturating_add(viewport.1.x - viewport.0.x + 1),
+            end.y.saturating_add(viewport.1.y - viewport.0.y + 1),
+        );
+        let span = (end.x - start.x + 1, end.y - start.y + 1);
+
+        // Blocks grow until the minimap fits in a third of the width and half the canvas
+        let room = (
+            (width / 3).saturating_sub(2).max(1),
+            (height.saturating_sub(ToolBar::HEIGHT + 1) / 2).saturating_sub(2).max(1),
+        );
+        let scale = 1.max(span.0.div_ceil(4 * room.0)).max(span.1.div_ceil(2 * room.1));
+        let block = (4 * scale, 2 * scale);
+        let (columns, rows) = (span.0.div_ceil(block.0), span.1.div_ceil(block.1));
+
+        let mut drawn: HashMap<(u16, u16), u16> = HashMap::new();
+        for cell in cells().filter(|cell| !cell.is_blank()) {
+            let pos = cell.pos();
+            *drawn
+                .entry(((pos.x - start.x) / block.0, (pos.y - start.y) / block.1))
+                .or_default() += 1;
+        }
+
+        let x = width.saturating_sub(columns + 1);
+        let y = height.saturating_sub(rows + 2);
+        let format = theme.toolbar.format();
+        let title = "─Map";
+        let rule = "─".repeat(columns.saturating_sub(grid::text_width(title) as u16) as usize);
+        let mut segments = vec![
+            str_to_segment((x, y), &format!("┌{}{}┐", title, rule), format),
+            str_to_segment(
+                (x, y + rows + 1),
+                &format!("└{}┘", "─".repeat(columns as usize)),
+                format,
+            ),
+        ];
+
+        let mut blocks = grid::Segment::new();
+        blocks.set_format(format);
+        let mut visible = grid::Segment::new();
+        visible.set_format(theme.selection.format());
+        let total = (block.0 * block.1) as usize;
+        for row in 0..rows {
+            segments.push(str_to_segment((x, y + 1 + row), "│", format));
+            segments.push(str_to_segment((x + columns + 1, y + 1 + row), "│", format));
+            for column in 0..columns {
+                let count = drawn.get(&(column, row)).copied().unwrap_or(0) as usize;
+                // Round up, so a single cell still shows
+                let shade = DENSITY[(count * (DENSITY.len() - 1)).div_ceil(total)];
+                let (left, top) = (start.x + column * block.0, start.y + row * block.1);
+                let on_screen = left <= viewport.1.x
+                    && left + block.0 > viewport.0.x
+                    && top <= viewport.1.y
+                    && top + block.1 > viewport.0.y;
+                let segment = if on_screen { &mut visible } else { &mut blocks };
+                segment.add(grid::Cell::new(grid::Point::new(x + 1 + column, y + 1 + row), shade));
+            }
+        }
+        segments.push(blocks);
+        segments.push(visible);
+
+        let bounds = (grid::Point::new(x, y), grid::Point::new(x + columns + 1, y + rows + 1));
+        Self { segments, bounds, start, block }
+    }
+
+    pub fn contains(&self, pos: grid::Point) -> bool {
+        let (start, end) = self.bounds;
+        (start.x..=end.x).contains(&pos.x) && (start.y..=end.y).contains(&pos.y)
+    }
+
+    /// The canvas cell in the middle of the block shown at `pos` on screen, if it's inside the
+    /// map's border.
+    pub fn block_at(&self, pos: grid::Point) -> Option<grid::Point> {
+        let (start, end) = self.bounds;
+        if pos.x <= start.x || pos.x >= end.x || pos.y <= start.y || pos.y >= end.y {
+            return None;
+        }
+        let (column, row) = (pos.x - start.x - 1, pos.y - start.y - 1);
+        Some(grid::Point::new(
+            self.start.x + column * self.block.0 + self.block.0 / 2,
+            self.start.y + row * self.block.1 + self.block.1 / 2,
+        ))
+    }
+
+    pub fn segment(&self) -> grid::Segment {
+        self.segments.iter().sum()
+    }
+}
+
+impl fmt::Display for Minimap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.segments.iter().try_for_each(|segment| write!(f, "{}", segment))
+    }
+}
+
+pub struct Prompt {
+    label: String,
+    input: String,
+    pos: grid::Point,
+    width: u16,
+    theme: theme::Theme,
+}
+
+impl Prompt {
+    pub fn new(label: &str, pos: grid::Point, width: u16, theme: theme::Theme) -> Self {
+        Self { label: label.to_string(), input: String::new(), pos, width, theme }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.input
+    }
+
+    pub fn set_value(&mut self, value: &str) {
+        self.input = value.to_string();
+    }
+
+    pub fn push(&mut self, char: char) {
+        self.input.push(char);
+    }
+
+    pub fn pop(&mut self) {
+        self.input.pop();
+    }
+
+    pub fn complete_path(&mut self, base: &path::Path) {
+        if let Some(completion) = complete_path(base, &self.input) {
+            self.input = completion;
+        }
+    }
+
+    pub fn segment(&self) -> grid::Segment {
+        self.segments().iter().sum()
+    }
+
+    /// The label and input, the caret after them, and the padding up to `width`.
+    fn segments(&self) -> [grid::Segment; 3] {
+        let text = format!("{}{}", self.label, self.input);
+        let caret = grid::Point::new(self.pos.x + grid::text_width(&text) as u16, self.pos.y);
+        let padding = (self.width as usize).saturating_sub(grid::text_width(&text) + 1);
+        [
+            grid::Segment::from_str(self.pos, &text, self.theme.highlight.format()),
+            grid::Segment::from_str(caret, "_", self.theme.cursor.format()),
+            grid::Segment::from_str(
+                grid::Point::new(caret.x + 1, caret.y),
+                &" ".repeat(padding),
+                self.theme.highlight.format(),
+            ),
+        ]
+    }
+}
+
+impl fmt::Display for Prompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.segments().iter().try_for_each(|segment| write!(f, "{}", segment))
+    }
+}
+
+/// Pads `text` out to `width` columns, or cuts it short there.
+fn fit(text: String, width: usize) -> String {
+    let mut fitted = String::new();
+    for char in text.chars() {
+        fitted.push(char);
+        if grid::text_width(&fitted) > width {
+            fitted.pop();
+            break;
+        }
+    }
+    pad(fitted, width)
+}
+
+/// Pads `text` with spaces up to `width` columns, counting wide characters as two.
+fn pad(mut text: String, width: usize) -> String {
+    let padding = width.saturating_sub(grid::text_width(&text));
+    text.push_str(&" ".repeat(padding));
+    text
+}
+
+/// Extends `input` to the longest prefix shared by all entries it could name, relative to
+/// `base`. Directories are completed with a trailing `/`.
+pub fn complete_path(base: &path::Path, input: &str) -> Option<String> {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(index) => input.split_at(index + 1),
+        None => ("", input),
+    };
+
+    let candidates: Vec<String> = fs::read_dir(base.join(dir))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let mut name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) {
+                return None;
+            }
+            if entry.path().is_dir() {
+                name.push('/');
+            }
+            Some(name)
+        })
+        .collect();
+
+    let first = candidates.first()?;
+    let shared = candidates.iter().fold(first.chars().count(), |len, candidate| {
+        first.chars().zip(candidate.chars()).take(len).take_while(|(a, b)| a == b).count()
+    });
+
+    Some(format!("{}{}", dir, first.chars().take(shared).collect::<String>()))
+}
+
+pub fn next_color(color: terminal::Color) -> terminal::Color {
+    let index = PALETTE.iter().position(|c| *c == color).map_or(0, |i| i + 1);
+    PALETTE[index % PALETTE.len()]
+}
+
+/// Swatches for each colour in the palette after `label`, a column apart unless `compact`.
+fn palette_row(
+    x: u16,
+    label: &str,
+    selected: terminal::Color,
+    compact: bool,
+    theme: &theme::Theme,
+) -> Vec<grid::Segment> {
+    let step = if compact { 2 } else { 3 };
+    let mut row = vec![str_to_segment((x, 3), label, theme.toolbar.format())];
+    for (i, color) in PALETTE.iter().enumerate() {
+        let pos = grid::Point::new(x + grid::text_width(label) as u16 + 1 + step * i as u16, 3);
+        let swatch = if *color == selected {
+            let contrast = match color {
+                terminal::Color::Black => terminal::Color::White,
+                _ => terminal::Color::Black,
+            };
+            grid::Segment::from_str(pos, "**", terminal::Format::new(*color, contrast))
+        } else {
+            grid::Segment::from_str(pos, "  ", terminal::Format::new(*color, *color))
+        };
+        row.push(swatch);
+    }
+    row
+}
+
+fn palette_width(label: &str, compact: bool) -> u16 {
+    let step = if compact { 2 } else { 3 };
+    grid::text_width(label) as u16 + 1 + step * PALETTE.len() as u16 - (step - 2)
+}
+
+/// Shows the key toggling each attribute, styled with that attribute and highlighted when set.
+fn attribute_row(
+    x: u16,
+    selected: terminal::Attributes,
+    keymap: &keymap::Keymap,
+    theme: &theme::Theme,
+) -> Vec<grid::Segment> {
+    let mut pos = grid::Point::new(x, 3);
+    terminal::Attribute::ALL
+        .iter()
+        .map(|attribute| {
+            let key = keymap.label(Action::ToggleAttribute(*attribute));
+            let format = if selected.has(*attribute) { theme.highlight } else { theme.toolbar };
+            let segment = grid::Segment::from_str(
+                pos,
+                &key,
+                format.format().with_attributes((*attribute).into()),
+            );
+            pos.x += grid::text_width(&key) as u16 + 1;
+            segment
+        })
+        .collect()
+}
+
+fn str_to_segment((x, y): (u16, u16), text: &str, format: terminal::Format) -> grid::Segment {
+    grid::Segment::from_str(grid::Point::new(x, y), text, format)
 }
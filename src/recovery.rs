@@ -0,0 +1,176 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path;
+use std::process;
+
+use serde::{Deserialize, Serialize};
+
+use crate::canvas;
+
+/// An open sketch as kept in the recovery file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Sketch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    pub canvas: canvas::Record,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Contents {
+    sketches: Vec<Sketch>,
+}
+
+/// The recovery file of a running session, named after its process ID so that sessions open at
+/// the same time don't write over or delete each other's. Each session holds a lock on a file
+/// beside its own for as long as it runs, which is how the files left behind by a crash are told
+/// apart from those still in use.
+pub struct Session {
+    dir: Option<path::PathBuf>,
+    name: String,
+    /// Kept open to hold the locks: this session's, then those of the files it took over.
+    locks: Vec<fs::File>,
+    /// Files left by sessions that ended without cleaning up, removed once this session has
+    /// saved or discarded what it restored from them.
+    leftovers: Vec<path::PathBuf>,
+}
+
+fn state_dir() -> Option<path::PathBuf> {
+    let state_dir = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => path::PathBuf::from(dir),
+        _ => path::PathBuf::from(env::var_os("HOME")?).join(".local").join("state"),
+    };
+    Some(state_dir.join("shketch"))
+}
+
+/// Opens and locks the lock file for the recovery file at `path`, or returns `None` if another
+/// session holds it.
+fn lock(path: &path::Path) -> io::Result<Option<fs::File>> {
+    let file = fs::OpenOptions::new().create(true).truncate(false).write(true).open(path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(fs::TryLockError::WouldBlock) => Ok(None),
+        Err(fs::TryLockError::Error(error)) => Err(error),
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        let mut session = Self {
+            dir: state_dir(),
+            name: format!("recovery-{}", process::id()),
+            locks: Vec::new(),
+            leftovers: Vec::new(),
+        };
+        // Without its lock, the file can still be written, but another session could take it
+        // over after a crash of this one
+        if let (Some(dir), Some(path)) = (&session.dir, session.path()) {
+            let locked = fs::create_dir_all(dir).and_then(|()| lock(&path.with_extension("lock")));
+            if let Ok(Some(file)) = locked {
+                session.locks.push(file);
+            }
+        }
+        session
+    }
+
+    fn path(&self) -> Option<path::PathBuf> {
+        Some(self.dir.as_ref()?.join(&self.name).with_extension("toml"))
+    }
+
+    /// Writes `sketches`, with their layers and everything on them, to this session's file.
+    pub fn save(&mut self, sketches: Vec<Sketch>) -> crate::Result {
+        let path = self.path().ok_or("could not determine state directory")?;
+        if sketches.iter().all(|sketch| sketch.canvas.is_empty()) {
+            return self.discard();
+        }
+
+        // Written beside it first, so that a crash halfway through leaves the last one intact
+        let content = toml::to_string(&Contents { sketches })?;
+        let partial = path.with_extension("toml.partial");
+        let mut file = fs::File::create(&partial)?;
+        file.write_all(content.as_bytes())?;
+        fs::rename(partial, path)?;
+        self.remove_leftovers();
+        Ok(())
+    }
+
+    /// Takes over the files left by sessions that are no longer running, and reads back their
+    /// sketches. A file that can't be read is moved aside, so that it doesn't come up again in
+    /// every later session, with a message saying where to.
+    pub fn load(&mut self) -> (Vec<Sketch>, Vec<String>) {
+        let (mut sketches, mut errors) = (Vec::new(), Vec::new());
+        let entries = match self.dir.as_deref().map(fs::read_dir) {
+            Some(Ok(entries)) => entries,
+            _ => return (sketches, errors),
+        };
+        let mut paths: Vec<path::PathBuf> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                let stem = path.file_stem().and_then(|stem| stem.to_str());
+                path.extension().is_some_and(|extension| extension == "toml")
+                    && stem.is_some_and(|stem| stem.starts_with("recovery-") && stem != self.name)
+            })
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let lock_path = path.with_extension("lock");
+            let file = match lock(&lock_path) {
+                Ok(Some(file)) => file,
+                // Still in use, or can't be told either way
+                Ok(None) | Err(_) => continue,
+            };
+            match read(&path) {
+                Ok(contents) => {
+                    sketches.extend(contents.sketches);
+                    self.locks.push(file);
+                    self.leftovers.push(path);
+                }
+                Err(error) => {
+                    let aside = path.with_extension("toml.bad");
+                    let message = match fs::rename(&path, &aside) {
+                        Ok(()) => format!(
+                            "couldn't restore a crashed session ({}), moved to {}",
+                            error,
+                            aside.display()
+                        ),
+                        Err(_) => format!("couldn't restore {}: {}", path.display(), error),
+                    };
+                    errors.push(message);
+                    let _ = fs::remove_file(lock_path);
+                }
+            }
+        }
+        (sketches, errors)
+    }
+
+    /// Removes this session's file, along with any it took over, once its work is safely saved.
+    pub fn discard(&mut self) -> crate::Result {
+        self.remove_leftovers();
+        match self.path().map(fs::remove_file) {
+            Some(Err(error)) if error.kind() != io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn remove_leftovers(&mut self) {
+        for path in self.leftovers.drain(..) {
+            let _ = fs::remove_file(&path);
+            let _ = fs::remove_file(path.with_extension("lock"));
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // Only once the lock is no longer needed, on the way out
+        if let Some(path) = self.path().filter(|path| !path.exists()) {
+            let _ = fs::remove_file(path.with_extension("lock"));
+        }
+    }
+}
+
+fn read(path: &path::Path) -> crate::Result<Contents> {
+    let content = fs::read_to_string(path)?;
+    Ok(toml::from_str(&content)?)
+}
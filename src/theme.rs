@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::Color;
+
+/// Colours for the parts of the screen that aren't the sketch itself. Themes defined in the
+/// config file start from the dark theme, so they only need to list what they change.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// Toolbar labels.
+    pub toolbar: Style,
+    /// The selected tool, resolution and attributes, and prompts.
+    pub highlight: Style,
+    /// Layer, brush and modification status.
+    pub status: Style,
+    /// Notifications in the status bar, like the file last saved to.
+    pub banner: Style,
+    /// Handles around the selected shape.
+    pub selection: Style,
+    /// The caret at the end of prompts.
+    pub cursor: Style,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+}
+
+impl Style {
+    const fn new(fg: terminal::Color, bg: terminal::Color) -> Self {
+        Self { fg: Color(fg), bg: Color(bg) }
+    }
+
+    pub fn format(self) -> terminal::Format {
+        terminal::Format::new(self.bg.0, self.fg.0)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        DARK
+    }
+}
+
+#[rustfmt::skip]
+pub static DARK: Theme = Theme {
+    toolbar: Style::new(terminal::Color::Reset, terminal::Color::Reset),
+    highlight: Style::new(terminal::Color::Black, terminal::Color::White),
+    status: Style::new(terminal::Color::Reset, terminal::Color::Reset),
+    banner: Style::new(terminal::Color::Green, terminal::Color::Black),
+    selection: Style::new(terminal::Color::Black, terminal::Color::White),
+    cursor: Style::new(terminal::Color::Black, terminal::Color::White),
+};
+
+#[rustfmt::skip]
+pub static LIGHT: Theme = Theme {
+    toolbar: Style::new(terminal::Color::Black, terminal::Color::Reset),
+    highlight: Style::new(terminal::Color::White, terminal::Color::DarkBlue),
+    status: Style::new(terminal::Color::DarkGrey, terminal::Color::Reset),
+    banner: Style::new(terminal::Color::DarkGreen, terminal::Color::Reset),
+    selection: Style::new(terminal::Color::White, terminal::Color::DarkMagenta),
+    cursor: Style::new(terminal::Color::White, terminal::Color::Black),
+};
+
+#[rustfmt::skip]
+pub static HIGH_CONTRAST: Theme = Theme {
+    toolbar: Style::new(terminal::Color::White, terminal::Color::Black),
+    highlight: Style::new(terminal::Color::Black, terminal::Color::Yellow),
+    status: Style::new(terminal::Color::Yellow, terminal::Color::Black),
+    banner: Style::new(terminal::Color::Black, terminal::Color::Green),
+    selection: Style::new(terminal::Color::Black, terminal::Color::Magenta),
+    cursor: Style::new(terminal::Color::Black, terminal::Color::Cyan),
+};
+
+/// Built-in themes, in the order they're cycled through before any from the config file.
+pub static BUILT_IN: [(&str, &Theme); 3] =
+    [("dark", &DARK), ("light", &LIGHT), ("high-contrast", &HIGH_CONTRAST)];